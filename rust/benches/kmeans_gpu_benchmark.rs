@@ -39,6 +39,8 @@ fn benchmark_kmeans_gpu(c: &mut Criterion) {
             algorithm: algorithm.into(),
             initializer: Initializer::Random,
             seed: Some(42),
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
         };
         let kmeans = block_on(KMeansGpu::new(config));
 
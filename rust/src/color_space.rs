@@ -0,0 +1,230 @@
+use crate::types::Vec4;
+
+// The space colors are clustered in. Distances in sRGB don't track perceived
+// differences well, so palettes built there spend too many colors on greens and too few
+// on dark tones. CIELAB and OKLab are close to perceptually uniform.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    // Linear light, scaled to 0-255
+    LinearRgb,
+    // CIELAB with a D65 white point. L runs from 0 to 100.
+    Lab,
+    // OKLab (Ottosson, 2020), scaled by 100 so that L runs from 0 to 100 like CIELAB's
+    Oklab,
+}
+
+const SRGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+];
+const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+const D65_WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+const LAB_DELTA: f64 = 6.0 / 29.0;
+
+const LINEAR_TO_LMS: [[f64; 3]; 3] = [
+    [0.4122214708, 0.5363325363, 0.0514459929],
+    [0.2119034982, 0.6806995451, 0.1073969566],
+    [0.0883024619, 0.2817188376, 0.6299787005],
+];
+const LMS_TO_OKLAB: [[f64; 3]; 3] = [
+    [0.2104542553, 0.7936177850, -0.0040720468],
+    [1.9779984951, -2.4285922050, 0.4505937099],
+    [0.0259040371, 0.7827717662, -0.8086757660],
+];
+const OKLAB_TO_LMS: [[f64; 3]; 3] = [
+    [1.0, 0.3963377774, 0.2158037573],
+    [1.0, -0.1055613458, -0.0638541728],
+    [1.0, -0.0894841775, -1.2914855480],
+];
+const LMS_TO_LINEAR: [[f64; 3]; 3] = [
+    [4.0767416621, -3.3077115913, 0.2309699292],
+    [-1.2684380046, 2.6097574011, -0.3413193965],
+    [-0.0041960863, -0.7034186147, 1.7076147010],
+];
+const OKLAB_SCALE: f64 = 100.0;
+
+// One scale for every channel that fits the sRGB gamut into 0-255, then an offset that
+// puts the gray axis at a and b of 128, where the gray diagonal of sRGB runs
+const LAB_FIT: (f32, [f32; 3]) = (1.15, [0.0, 128.0, 128.0]);
+const OKLAB_FIT: (f32, [f32; 3]) = (2.5, [0.0, 128.0, 128.0]);
+
+fn multiply(matrix: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+// sRGB transfer functions, on channels from 0 to 1
+fn decode(channel: f64) -> f64 {
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn encode(channel: f64) -> f64 {
+    if channel <= 0.0031308 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn lab_f(t: f64) -> f64 {
+    if t > LAB_DELTA.powi(3) {
+        t.cbrt()
+    } else {
+        t / (3.0 * LAB_DELTA * LAB_DELTA) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inverse(t: f64) -> f64 {
+    if t > LAB_DELTA {
+        t.powi(3)
+    } else {
+        3.0 * LAB_DELTA * LAB_DELTA * (t - 4.0 / 29.0)
+    }
+}
+
+fn linear_to_lab(rgb: [f64; 3]) -> [f64; 3] {
+    let xyz = multiply(&SRGB_TO_XYZ, rgb);
+    let [fx, fy, fz] = [0, 1, 2].map(|i| lab_f(xyz[i] / D65_WHITE[i]));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn lab_to_linear(lab: [f64; 3]) -> [f64; 3] {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let f = [fy + lab[1] / 500.0, fy, fy - lab[2] / 200.0];
+    let xyz = [0, 1, 2].map(|i| lab_f_inverse(f[i]) * D65_WHITE[i]);
+    multiply(&XYZ_TO_SRGB, xyz)
+}
+
+fn linear_to_oklab(rgb: [f64; 3]) -> [f64; 3] {
+    let lms = multiply(&LINEAR_TO_LMS, rgb).map(f64::cbrt);
+    multiply(&LMS_TO_OKLAB, lms).map(|channel| channel * OKLAB_SCALE)
+}
+
+fn oklab_to_linear(lab: [f64; 3]) -> [f64; 3] {
+    let lms = multiply(&OKLAB_TO_LMS, lab.map(|channel| channel / OKLAB_SCALE));
+    multiply(&LMS_TO_LINEAR, lms.map(|channel| channel.powi(3)))
+}
+
+impl ColorSpace {
+    // Converts an sRGB color with channels from 0 to 255. Alpha is kept as is.
+    pub fn from_srgb(self, color: Vec4) -> Vec4 {
+        let linear = || [0, 1, 2].map(|i| decode(color[i] as f64 / 255.0));
+        let [x, y, z] = match self {
+            ColorSpace::Srgb => return color,
+            ColorSpace::LinearRgb => linear().map(|channel| channel * 255.0),
+            ColorSpace::Lab => linear_to_lab(linear()),
+            ColorSpace::Oklab => linear_to_oklab(linear()),
+        };
+        [x as f32, y as f32, z as f32, color[3]]
+    }
+
+    // Converts back to sRGB with channels from 0 to 255, rounded. Colors outside the
+    // sRGB gamut are clamped to it.
+    pub fn to_srgb(self, color: Vec4) -> Vec4 {
+        let rgb = [color[0], color[1], color[2]].map(|channel| channel as f64);
+        let linear = match self {
+            ColorSpace::Srgb => return color,
+            ColorSpace::LinearRgb => rgb.map(|channel| channel / 255.0),
+            ColorSpace::Lab => lab_to_linear(rgb),
+            ColorSpace::Oklab => oklab_to_linear(rgb),
+        };
+        let [r, g, b] = linear.map(|channel| (encode(channel.clamp(0.0, 1.0)) * 255.0).round());
+        [r as f32, g as f32, b as f32, color[3]]
+    }
+
+    fn fit(self) -> (f32, [f32; 3]) {
+        match self {
+            ColorSpace::Srgb | ColorSpace::LinearRgb => (1.0, [0.0; 3]),
+            ColorSpace::Lab => LAB_FIT,
+            ColorSpace::Oklab => OKLAB_FIT,
+        }
+    }
+
+    // Colors are clustered with the space scaled and shifted so that sRGB colors land in
+    // 0-255, as Octree, Wu and NeuQuant bin and clamp channels like bytes and the GPU
+    // reads whole numbers. The scale is uniform, so distances keep their proportions.
+    pub(crate) fn srgb_to_clustering(self, color: Vec4) -> Vec4 {
+        let (scale, offset) = self.fit();
+        let color = self.from_srgb(color);
+        [
+            color[0] * scale + offset[0],
+            color[1] * scale + offset[1],
+            color[2] * scale + offset[2],
+            color[3],
+        ]
+    }
+
+    pub(crate) fn clustering_to_srgb(self, color: Vec4) -> Vec4 {
+        let (scale, offset) = self.fit();
+        self.to_srgb([
+            (color[0] - offset[0]) / scale,
+            (color[1] - offset[1]) / scale,
+            (color[2] - offset[2]) / scale,
+            color[3],
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_space_round_trips_srgb_bytes() {
+        let spaces = [
+            ColorSpace::Srgb,
+            ColorSpace::LinearRgb,
+            ColorSpace::Lab,
+            ColorSpace::Oklab,
+        ];
+        for space in spaces {
+            for r in (0..=255).step_by(15) {
+                for g in (0..=255).step_by(17) {
+                    for b in (0..=255).step_by(51) {
+                        let color = [r as f32, g as f32, b as f32, 255.0];
+                        assert_eq!(space.to_srgb(space.from_srgb(color)), color, "{:?}", space);
+
+                        let clustered = space.srgb_to_clustering(color);
+                        assert!(
+                            clustered.iter().all(|&c| (0.0..=255.0).contains(&c)),
+                            "{:?} {:?}",
+                            space,
+                            clustered
+                        );
+                        assert_eq!(space.clustering_to_srgb(clustered), color, "{:?}", space);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reference_colors_and_gamut_clamping() {
+        let white = ColorSpace::Lab.from_srgb([255.0, 255.0, 255.0, 255.0]);
+        assert!((white[0] - 100.0).abs() < 1e-2);
+        assert!(white[1].abs() < 1e-2 && white[2].abs() < 1e-2);
+
+        // Red in OKLab is (0.628, 0.225, 0.126)
+        let red = ColorSpace::Oklab.from_srgb([255.0, 0.0, 0.0, 255.0]);
+        assert!((red[0] - 62.8).abs() < 0.1);
+        assert!((red[1] - 22.5).abs() < 0.1);
+        assert!((red[2] - 12.6).abs() < 0.1);
+
+        // Far more saturated than any sRGB green
+        let green = ColorSpace::Lab.to_srgb([50.0, -150.0, 80.0, 128.0]);
+        assert!(green
+            .iter()
+            .all(|&channel| (0.0..=255.0).contains(&channel)));
+        assert_eq!(green[3], 128.0);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::types::{Vec4, Vec4u};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+// A color and how much of the image it covers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorCount {
+    pub color: [u8; 4],
+    pub count: usize,
+    // Percentage of the counted pixels, from 0 to 100
+    pub coverage: f32,
+}
+
+// Exact count of every RGBA color in an image. Colors are keyed by their packed
+// 32-bit value, and kept in order of first appearance so that anything built from
+// the histogram is reproducible. Pixels can also carry a weight, which is summed per
+// color alongside the count.
+#[derive(Debug, Clone, Default)]
+pub struct ColorHistogram {
+    colors: Vec<u32>,
+    counts: Vec<usize>,
+    weights: Vec<f32>,
+    indices: HashMap<u32, usize>,
+    total: usize,
+}
+
+fn pack(color: [u8; 4]) -> u32 {
+    u32::from_be_bytes(color)
+}
+
+fn unpack(packed: u32) -> [u8; 4] {
+    packed.to_be_bytes()
+}
+
+impl ColorHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Counts interleaved pixel bytes. Images without an alpha channel count as opaque.
+    pub fn from_bytes(pixels: &[u8], channels: usize) -> Self {
+        let mut histogram = Self::new();
+        for pixel in pixels.chunks_exact(channels) {
+            let alpha = if channels >= 4 { pixel[3] } else { u8::MAX };
+            histogram.insert([pixel[0], pixel[1], pixel[2], alpha]);
+        }
+        histogram
+    }
+
+    pub fn from_pixels(pixels: &[Vec4u]) -> Self {
+        let mut histogram = Self::new();
+        for pixel in pixels {
+            histogram.insert(pixel.map(|channel| channel as u8));
+        }
+        histogram
+    }
+
+    // Counts the pixels with a positive weight. Pixels with a weight of 0 are left out.
+    pub fn from_pixels_weighted(pixels: &[Vec4u], weights: &[f32]) -> Self {
+        let mut histogram = Self::new();
+        for (pixel, &weight) in pixels.iter().zip(weights.iter()) {
+            if weight > 0.0 {
+                histogram.insert_weighted(pixel.map(|channel| channel as u8), weight);
+            }
+        }
+        histogram
+    }
+
+    pub fn insert(&mut self, color: [u8; 4]) {
+        self.insert_weighted(color, 1.0);
+    }
+
+    pub fn insert_weighted(&mut self, color: [u8; 4], weight: f32) {
+        let packed = pack(color);
+        match self.indices.get(&packed) {
+            Some(&index) => {
+                self.counts[index] += 1;
+                self.weights[index] += weight;
+            }
+            None => {
+                self.indices.insert(packed, self.colors.len());
+                self.colors.push(packed);
+                self.counts.push(1);
+                self.weights.push(weight);
+            }
+        }
+        self.total += 1;
+    }
+
+    // Number of distinct colors
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    // Number of pixels counted
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    // Number of distinct colors when alpha is ignored
+    pub fn len_rgb(&self) -> usize {
+        self.colors
+            .iter()
+            .map(|&packed| packed >> 8)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    pub fn count(&self, color: [u8; 4]) -> usize {
+        self.indices
+            .get(&pack(color))
+            .map_or(0, |&index| self.counts[index])
+    }
+
+    pub fn coverage(&self, color: [u8; 4]) -> f32 {
+        self.percentage(self.count(color))
+    }
+
+    fn percentage(&self, count: usize) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        (count as f64 * 100.0 / self.total as f64) as f32
+    }
+
+    // Every color, in order of first appearance
+    pub fn iter(&self) -> impl Iterator<Item = ColorCount> + '_ {
+        self.colors
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(&packed, &count)| ColorCount {
+                color: unpack(packed),
+                count,
+                coverage: self.percentage(count),
+            })
+    }
+
+    // The n most common colors, most common first. Ties keep their order of appearance.
+    pub fn top(&self, n: usize) -> Vec<ColorCount> {
+        let mut colors: Vec<ColorCount> = self.iter().collect();
+        colors.sort_by_key(|color| Reverse(color.count));
+        colors.truncate(n);
+        colors
+    }
+
+    // The distinct colors with their total weights, for weighted clustering. Without
+    // weighted inserts, the weights are the counts.
+    pub fn weighted_colors(&self) -> (Vec<Vec4>, Vec<f32>) {
+        self.colors
+            .iter()
+            .map(|&packed| unpack(packed).map(|channel| channel as f32))
+            .zip(self.weights.iter().copied())
+            .unzip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colors_that_used_to_collide_are_distinct() {
+        // 2r + 3g + 5b is 10 for all of these
+        let pixels: Vec<Vec4u> = vec![
+            [5, 0, 0, 255],
+            [0, 0, 2, 255],
+            [2, 2, 0, 255],
+            [5, 0, 0, 255],
+        ];
+
+        let histogram = ColorHistogram::from_pixels(&pixels);
+
+        assert_eq!(histogram.len(), 3);
+        assert_eq!(histogram.total(), 4);
+        assert_eq!(histogram.count([5, 0, 0, 255]), 2);
+        assert_eq!(histogram.count([1, 1, 1, 255]), 0);
+    }
+
+    #[test]
+    fn test_top_colors_and_coverage() {
+        let pixels = [
+            10, 20, 30, 40, 50, 60, 10, 20, 30, 70, 80, 90, 10, 20, 30, 40, 50, 60,
+        ];
+
+        let histogram = ColorHistogram::from_bytes(&pixels, 3);
+        let top = histogram.top(2);
+
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].color, [10, 20, 30, 255]);
+        assert_eq!(top[0].count, 3);
+        assert!((top[0].coverage - 50.0).abs() < 1e-4);
+        assert_eq!(top[1].color, [40, 50, 60, 255]);
+        assert!((histogram.coverage([70, 80, 90, 255]) - 100.0 / 6.0).abs() < 1e-4);
+        assert_eq!(histogram.len_rgb(), 3);
+    }
+}
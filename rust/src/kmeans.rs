@@ -1,24 +1,43 @@
+pub mod adaptive;
+pub mod auto_k;
+pub mod bisecting;
 mod config;
 pub mod distance;
+pub mod elkan;
+pub mod fuzzy;
+pub mod gmm;
 pub mod hamerly;
 pub mod initializer;
+pub mod kmedoids;
 pub mod lloyd;
+pub mod median_cut;
+pub mod minibatch;
+pub mod neuquant;
+pub mod octree;
+pub mod partition;
+pub mod pnn;
 mod types;
 mod utils;
+pub mod wu;
+pub mod yinyang;
 
 pub mod gpu;
 
 #[cfg(feature = "gpu")]
 use self::gpu::KMeansGpu;
 
-pub use crate::kmeans::config::{KMeansAlgorithm, KMeansConfig};
+pub use crate::kmeans::config::{EmptyClusterPolicy, KMeansAlgorithm, KMeansConfig};
 pub use crate::kmeans::initializer::Initializer;
-pub use crate::kmeans::utils::find_closest_centroid;
-use crate::utils::num_distinct_colors;
+pub use crate::kmeans::types::KMeansError;
+pub use crate::kmeans::utils::{find_closest_centroid, snap_to_members};
 
 use crate::types::{Vec3, Vec4, Vec4u, VectorExt};
 
-use self::types::{KMeansError, KMeansResult};
+use self::distance::SquaredEuclideanDistance;
+use self::types::{Assignments, Centroids, KMeansResult};
+use self::utils::{
+    fill_binned_palette, inertia, num_distinct_colors, restart_seed, weighted_inertia,
+};
 
 const DEFAULT_INITIALIZER: Initializer = Initializer::KMeansPlusPlus;
 
@@ -56,6 +75,25 @@ impl KMeansCPU {
         self.0.seed = Some(seed);
         self
     }
+
+    pub fn with_initializer(mut self, initializer: Initializer) -> Self {
+        self.0.initializer = initializer;
+        self
+    }
+
+    pub fn with_n_init(mut self, n_init: usize) -> Self {
+        self.0.n_init = n_init;
+        self
+    }
+
+    pub fn with_empty_cluster_policy(mut self, policy: EmptyClusterPolicy) -> Self {
+        self.0.empty_cluster_policy = policy;
+        self
+    }
+
+    pub fn config(&self) -> &KMeansConfig {
+        &self.0
+    }
 }
 
 impl Default for KMeansCPU {
@@ -67,13 +105,51 @@ impl Default for KMeansCPU {
             algorithm: KMeansAlgorithm::Lloyd,
             initializer: DEFAULT_INITIALIZER,
             seed: None,
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
         })
     }
 }
 
 impl KMeansCPU {
     pub fn run<T: VectorExt>(&self, data: &[T]) -> KMeansResult<T> {
-        let unique_colors = num_distinct_colors(data);
+        // Mini-batch has to stay bounded on huge images, so it skips the full pass
+        // counting colors
+        if !matches!(self.0.algorithm, KMeansAlgorithm::MiniBatch { .. }) {
+            let unique_colors = num_distinct_colors(data);
+            if unique_colors < self.0.k {
+                return Err(KMeansError(format!(
+                    "Number of unique colors is less than k: {}",
+                    unique_colors
+                )));
+            }
+        }
+
+        self.best_of_restarts(
+            |config| Self::run_once(data, config),
+            |assignments, centroids| inertia(data, assignments, centroids),
+        )
+    }
+
+    // Clusters points that each stand for `weight` pixels, such as the distinct colors
+    // of an image with their counts. Points with zero weight don't move the centroids,
+    // but are still assigned. Only Lloyd, Hamerly and PNN support weights.
+    pub fn run_weighted<T: VectorExt>(&self, data: &[T], weights: &[f32]) -> KMeansResult<T> {
+        if data.len() != weights.len() {
+            return Err(KMeansError(format!(
+                "Expected one weight per point, got {} weights for {} points",
+                weights.len(),
+                data.len()
+            )));
+        }
+
+        let (points, point_weights): (Vec<T>, Vec<f32>) = data
+            .iter()
+            .zip(weights.iter())
+            .filter(|(_, &weight)| weight > 0.0)
+            .map(|(point, &weight)| (*point, weight))
+            .unzip();
+        let unique_colors = num_distinct_colors(&points);
         if unique_colors < self.0.k {
             return Err(KMeansError(format!(
                 "Number of unique colors is less than k: {}",
@@ -81,13 +157,115 @@ impl KMeansCPU {
             )));
         }
 
-        match self.0.algorithm {
-            KMeansAlgorithm::Lloyd => Ok(lloyd::kmeans_lloyd(data, &self.0)),
-            KMeansAlgorithm::Hamerly => Ok(hamerly::kmeans_hamerly(data, &self.0)),
+        let (_, centroids) = self.best_of_restarts(
+            |config| Self::run_weighted_once(&points, &point_weights, config),
+            |assignments, centroids| {
+                weighted_inertia(&points, &point_weights, assignments, centroids)
+            },
+        )?;
+        let assignments = data
+            .iter()
+            .map(|point| find_closest_centroid(point, &centroids))
+            .collect();
+        Ok((assignments, centroids))
+    }
+
+    // Whether run_weighted supports the configured algorithm
+    pub fn supports_weights(&self) -> bool {
+        matches!(
+            self.0.algorithm,
+            KMeansAlgorithm::Lloyd | KMeansAlgorithm::Hamerly | KMeansAlgorithm::Pnn
+        )
+    }
+
+    // Runs once per restart, each with its own seed, and keeps the result with the lowest
+    // inertia. Restart 0 uses the configured seed, so a single run is unchanged.
+    fn best_of_restarts<T: VectorExt>(
+        &self,
+        run: impl Fn(&KMeansConfig) -> KMeansResult<T>,
+        inertia: impl Fn(&[usize], &[T]) -> SquaredEuclideanDistance,
+    ) -> KMeansResult<T> {
+        if self.0.k == 0 {
+            return Err(KMeansError("k must be at least 1".to_string()));
+        }
+
+        let restarts = self.0.restarts();
+        let mut best: Option<(SquaredEuclideanDistance, Assignments, Centroids<T>)> = None;
+        for restart in 0..restarts {
+            let config = KMeansConfig {
+                seed: restart_seed(self.0.seed, restart),
+                ..self.0.clone()
+            };
+            let (assignments, centroids) = run(&config)?;
+            let inertia = inertia(&assignments, &centroids);
+            if best
+                .as_ref()
+                .is_none_or(|(best_inertia, _, _)| inertia < *best_inertia)
+            {
+                best = Some((inertia, assignments, centroids));
+            }
+        }
+        let (_, assignments, centroids) = best.unwrap();
+        Ok((assignments, centroids))
+    }
+
+    fn run_weighted_once<T: VectorExt>(
+        data: &[T],
+        weights: &[f32],
+        config: &KMeansConfig,
+    ) -> KMeansResult<T> {
+        match config.algorithm {
+            KMeansAlgorithm::Lloyd => Ok(lloyd::kmeans_lloyd_weighted(data, weights, config)),
+            KMeansAlgorithm::Hamerly => Ok(hamerly::kmeans_hamerly_weighted(data, weights, config)),
+            KMeansAlgorithm::Pnn => pnn::quantize_pnn_weighted(data, weights, config),
+            _ => Err(KMeansError(format!(
+                "Algorithm doesn't support weighted data: {}",
+                config.algorithm
+            ))),
+        }
+    }
+
+    fn run_once<T: VectorExt>(data: &[T], config: &KMeansConfig) -> KMeansResult<T> {
+        match config.algorithm {
+            KMeansAlgorithm::Lloyd => Ok(lloyd::kmeans_lloyd(data, config)),
+            KMeansAlgorithm::Hamerly => Ok(hamerly::kmeans_hamerly(data, config)),
+            KMeansAlgorithm::Elkan => Ok(elkan::kmeans_elkan(data, config)),
+            KMeansAlgorithm::Yinyang => Ok(yinyang::kmeans_yinyang(data, config)),
+            KMeansAlgorithm::MiniBatch { batch_size } => {
+                Ok(minibatch::kmeans_minibatch(data, config, batch_size))
+            }
+            KMeansAlgorithm::MedianCut => Ok(median_cut::quantize_median_cut(data, config)),
+            KMeansAlgorithm::Wu => Ok(fill_binned_palette(
+                data,
+                wu::quantize_wu(data, config),
+                config.k,
+            )),
+            KMeansAlgorithm::Octree => Ok(fill_binned_palette(
+                data,
+                octree::quantize_octree(data, config),
+                config.k,
+            )),
+            KMeansAlgorithm::NeuQuant { sample_factor } => Ok(fill_binned_palette(
+                data,
+                neuquant::quantize_neuquant(data, config, sample_factor),
+                config.k,
+            )),
+            KMeansAlgorithm::Bisecting => Ok(bisecting::kmeans_bisecting(data, config)),
+            KMeansAlgorithm::Pnn => pnn::quantize_pnn(data, config),
+            KMeansAlgorithm::Adaptive { split_test } => {
+                Ok(adaptive::kmeans_adaptive(data, config, split_test))
+            }
+            KMeansAlgorithm::FuzzyCMeans { fuzzifier } => {
+                Ok(fuzzy::kmeans_fuzzy(data, config, fuzzifier))
+            }
+            KMeansAlgorithm::GaussianMixture { covariance } => {
+                Ok(gmm::kmeans_gmm(data, config, covariance))
+            }
+            KMeansAlgorithm::KMedoids => Ok(kmedoids::kmeans_medoids(data, config)),
             #[cfg(feature = "gpu")]
             _ => Err(KMeansError(format!(
                 "Algorithm not supported on cpu: {}",
-                self.0.algorithm
+                config.algorithm
             ))),
         }
     }
@@ -108,6 +286,14 @@ impl KMeans {
         }
     }
 
+    pub fn config(&self) -> &KMeansConfig {
+        match self {
+            KMeans::Cpu(cpu) => cpu.config(),
+            #[cfg(feature = "gpu")]
+            KMeans::Gpu(gpu) => gpu.config(),
+        }
+    }
+
     #[cfg(feature = "gpu")]
     pub async fn gpu(self) -> Result<Self, &'static str> {
         use self::gpu::GpuAlgorithm;
@@ -178,6 +364,7 @@ impl KMeans {
 mod tests {
     use super::*;
     use crate::kmeans::config::{KMeansAlgorithm, KMeansConfig};
+    use crate::kmeans::gmm::CovarianceType;
     use futures::executor::block_on;
     use rand::rngs::StdRng;
     use rand::Rng;
@@ -207,7 +394,24 @@ mod tests {
     }
 
     fn run_kmeans_test(data: &[Vec3], k: usize, expected_non_empty_clusters: usize) {
-        let algorithms = vec![KMeansAlgorithm::Lloyd, KMeansAlgorithm::Hamerly];
+        let algorithms = vec![
+            KMeansAlgorithm::Lloyd,
+            KMeansAlgorithm::Hamerly,
+            KMeansAlgorithm::Elkan,
+            KMeansAlgorithm::Yinyang,
+            KMeansAlgorithm::MiniBatch { batch_size: 16 },
+            KMeansAlgorithm::MedianCut,
+            KMeansAlgorithm::Wu,
+            KMeansAlgorithm::Octree,
+            KMeansAlgorithm::NeuQuant { sample_factor: 1 },
+            KMeansAlgorithm::Bisecting,
+            KMeansAlgorithm::Pnn,
+            KMeansAlgorithm::FuzzyCMeans { fuzzifier: 2.0 },
+            KMeansAlgorithm::GaussianMixture {
+                covariance: CovarianceType::Full,
+            },
+            KMeansAlgorithm::KMedoids,
+        ];
 
         for algorithm in algorithms {
             let config = KMeansConfig {
@@ -217,6 +421,8 @@ mod tests {
                 algorithm,
                 initializer: DEFAULT_INITIALIZER,
                 seed: None,
+                n_init: 1,
+                empty_cluster_policy: Default::default(),
             };
 
             let (clusters, centroids) = KMeansCPU(config.clone()).run(data).unwrap();
@@ -276,6 +482,8 @@ mod tests {
             algorithm: KMeansAlgorithm::Lloyd,
             initializer: DEFAULT_INITIALIZER,
             seed: None,
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
         };
         let result = KMeansCPU(config).run(&data);
         assert_eq!(
@@ -308,6 +516,8 @@ mod tests {
             algorithm: KMeansAlgorithm::Lloyd,
             initializer: DEFAULT_INITIALIZER,
             seed: Some(seed),
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
         };
 
         let config_hamerly = KMeansConfig {
@@ -317,6 +527,30 @@ mod tests {
             algorithm: KMeansAlgorithm::Hamerly,
             initializer: DEFAULT_INITIALIZER,
             seed: Some(seed),
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
+        };
+
+        let config_elkan = KMeansConfig {
+            k: 3,
+            max_iterations: 500,
+            tolerance: 1e-6,
+            algorithm: KMeansAlgorithm::Elkan,
+            initializer: DEFAULT_INITIALIZER,
+            seed: Some(seed),
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
+        };
+
+        let config_yinyang = KMeansConfig {
+            k: 3,
+            max_iterations: 500,
+            tolerance: 1e-6,
+            algorithm: KMeansAlgorithm::Yinyang,
+            initializer: DEFAULT_INITIALIZER,
+            seed: Some(seed),
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
         };
 
         let config_gpu = KMeansConfig {
@@ -326,12 +560,16 @@ mod tests {
             algorithm: GpuAlgorithm::LloydAssignmentsOnly.into(),
             initializer: DEFAULT_INITIALIZER,
             seed: Some(seed),
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
         };
 
         let gpu = block_on(KMeansGpu::new(config_gpu));
 
         let (clusters1, centroids1) = KMeansCPU(config_lloyd).run(&data).unwrap();
         let (clusters2, centroids2) = KMeansCPU(config_hamerly).run(&data).unwrap();
+        let (clusters4, centroids4) = KMeansCPU(config_elkan).run(&data).unwrap();
+        let (clusters5, centroids5) = KMeansCPU(config_yinyang).run(&data).unwrap();
 
         let u32_data: Vec<Vec4u> = data
             .iter()
@@ -343,7 +581,271 @@ mod tests {
         dbg!(&centroids3);
 
         centroids1.assert_almost_eq(&centroids2, 1.0);
+        centroids1.assert_almost_eq(&centroids4, 1.0);
+        centroids1.assert_almost_eq(&centroids5, 1.0);
         centroids1.assert_almost_eq(&centroids3, 1.0);
         assert_eq!(clusters1, clusters2);
+        assert_eq!(clusters1, clusters4);
+        assert_eq!(clusters1, clusters5);
+    }
+
+    #[test]
+    fn test_yinyang_matches_lloyd_with_multiple_groups() {
+        let seed = 7;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let data = (0..2000)
+            .map(|_| {
+                [
+                    rng.gen::<f32>() * 255.0,
+                    rng.gen::<f32>() * 255.0,
+                    rng.gen::<f32>() * 255.0,
+                ]
+            })
+            .collect::<Vec<Vec3>>();
+
+        // k = 32 gives three centroid groups
+        let config = KMeansConfig {
+            k: 32,
+            max_iterations: 500,
+            tolerance: 1e-6,
+            algorithm: KMeansAlgorithm::Lloyd,
+            initializer: DEFAULT_INITIALIZER,
+            seed: Some(seed),
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
+        };
+
+        let (clusters_lloyd, centroids_lloyd) = KMeansCPU(config.clone()).run(&data).unwrap();
+        let (clusters_yinyang, centroids_yinyang) = KMeansCPU(config)
+            .with_algorithm(KMeansAlgorithm::Yinyang)
+            .run(&data)
+            .unwrap();
+
+        centroids_lloyd.assert_almost_eq(&centroids_yinyang, 1.0);
+        assert_eq!(clusters_lloyd, clusters_yinyang);
+    }
+
+    #[test]
+    fn test_warm_start_from_converged_centroids() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let data = (0..1000)
+            .map(|_| {
+                [
+                    rng.gen::<f32>() * 255.0,
+                    rng.gen::<f32>() * 255.0,
+                    rng.gen::<f32>() * 255.0,
+                ]
+            })
+            .collect::<Vec<Vec3>>();
+        let kmeans = KMeansCPU::default()
+            .with_k(8)
+            .with_tolerance(1e-6)
+            .with_max_iterations(500)
+            .with_seed(3);
+
+        let (clusters, centroids) = kmeans.run(&data).unwrap();
+        let provided: Vec<Vec4> = centroids.iter().map(|c| [c[0], c[1], c[2], 0.0]).collect();
+        for algorithm in [KMeansAlgorithm::Lloyd, KMeansAlgorithm::Hamerly] {
+            let (warm_clusters, warm_centroids) = kmeans
+                .clone()
+                .with_algorithm(algorithm)
+                .with_initializer(Initializer::Provided(provided.clone()))
+                .with_max_iterations(1)
+                .run(&data)
+                .unwrap();
+
+            warm_centroids.assert_almost_eq(&centroids, 1e-3);
+            assert_eq!(warm_clusters, clusters);
+        }
+    }
+
+    #[test]
+    fn test_restarts_keep_the_lowest_inertia() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let data = (0..1000)
+            .map(|_| {
+                [
+                    rng.gen::<f32>() * 255.0,
+                    rng.gen::<f32>() * 255.0,
+                    rng.gen::<f32>() * 255.0,
+                ]
+            })
+            .collect::<Vec<Vec3>>();
+
+        for algorithm in [KMeansAlgorithm::Lloyd, KMeansAlgorithm::Hamerly] {
+            let kmeans = KMeansCPU::default()
+                .with_k(12)
+                .with_seed(11)
+                .with_initializer(Initializer::Random)
+                .with_algorithm(algorithm);
+            let restart_inertias: Vec<f32> = (0..5)
+                .map(|restart| {
+                    let (clusters, centroids) =
+                        kmeans.clone().with_seed(11 + restart).run(&data).unwrap();
+                    inertia(&data, &clusters, &centroids).0
+                })
+                .collect();
+
+            let (clusters, centroids) = kmeans.clone().with_n_init(5).run(&data).unwrap();
+            let best = restart_inertias.iter().copied().fold(f32::MAX, f32::min);
+            assert_eq!(inertia(&data, &clusters, &centroids).0, best);
+            // Restarts are seeded from the config, so they're reproducible
+            let (_, again) = kmeans.with_n_init(5).run(&data).unwrap();
+            assert_eq!(centroids, again);
+        }
+    }
+
+    #[test]
+    fn test_restarts_only_run_when_the_seed_matters() {
+        let config = |algorithm, initializer| KMeansConfig {
+            algorithm,
+            initializer,
+            n_init: 5,
+            ..KMeansConfig::default()
+        };
+
+        assert_eq!(
+            config(KMeansAlgorithm::Lloyd, Initializer::KMeansPlusPlus).restarts(),
+            5
+        );
+        assert_eq!(
+            config(KMeansAlgorithm::Elkan, Initializer::Maximin).restarts(),
+            1
+        );
+        assert_eq!(
+            config(KMeansAlgorithm::Yinyang, Initializer::Wu).restarts(),
+            1
+        );
+        let neuquant = KMeansAlgorithm::NeuQuant { sample_factor: 1 };
+        assert_eq!(config(neuquant, Initializer::KMeansPlusPlus).restarts(), 1);
+        // Mini-batch samples its batches with the seed too
+        let minibatch = KMeansAlgorithm::MiniBatch { batch_size: 100 };
+        assert_eq!(config(minibatch, Initializer::Maximin).restarts(), 5);
+    }
+
+    #[test]
+    fn test_empty_clusters_follow_the_policy() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let data = (0..500)
+            .map(|_| {
+                [
+                    rng.gen::<f32>() * 50.0,
+                    rng.gen::<f32>() * 50.0,
+                    rng.gen::<f32>() * 50.0,
+                ]
+            })
+            .collect::<Vec<Vec3>>();
+        // The last centroid is too far away to ever get a pixel
+        let provided: Vec<Vec4> = vec![
+            [10.0, 10.0, 10.0, 0.0],
+            [40.0, 40.0, 40.0, 0.0],
+            [10.0, 40.0, 25.0, 0.0],
+            [255.0, 255.0, 255.0, 0.0],
+        ];
+        let algorithms = [
+            KMeansAlgorithm::Lloyd,
+            KMeansAlgorithm::Hamerly,
+            KMeansAlgorithm::Elkan,
+            KMeansAlgorithm::Yinyang,
+            KMeansAlgorithm::MiniBatch { batch_size: 64 },
+        ];
+
+        for algorithm in algorithms {
+            let kmeans = KMeansCPU::default()
+                .with_k(4)
+                .with_algorithm(algorithm.clone())
+                .with_initializer(Initializer::Provided(provided.clone()));
+
+            // By default the empty centroid stays where it was
+            let (clusters, centroids) = kmeans.run(&data).unwrap();
+            assert_eq!(centroids[3], [255.0, 255.0, 255.0], "{:?}", algorithm);
+            assert!(!clusters.contains(&3));
+
+            for policy in [
+                EmptyClusterPolicy::ReseedFarthest,
+                EmptyClusterPolicy::SplitLargest,
+            ] {
+                let (clusters, centroids) = kmeans
+                    .clone()
+                    .with_empty_cluster_policy(policy)
+                    .run(&data)
+                    .unwrap();
+                assert_eq!(centroids.len(), 4);
+                assert!(centroids.iter().flatten().all(|c| c.is_finite()));
+                assert!(centroids.iter().all(|c| c[0] < 50.0), "{:?}", algorithm);
+                assert!((0..4).all(|cluster| clusters.contains(&cluster)));
+            }
+
+            let (clusters, centroids) = kmeans
+                .with_empty_cluster_policy(EmptyClusterPolicy::Drop)
+                .run(&data)
+                .unwrap();
+            assert_eq!(centroids.len(), 3, "{:?}", algorithm);
+            assert!(clusters.iter().all(|&cluster| cluster < 3));
+        }
+    }
+
+    #[test]
+    fn test_weighted_run_matches_repeated_points() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let colors = (0..200)
+            .map(|_| {
+                [
+                    rng.gen::<f32>() * 255.0,
+                    rng.gen::<f32>() * 255.0,
+                    rng.gen::<f32>() * 255.0,
+                ]
+            })
+            .collect::<Vec<Vec3>>();
+        let weights: Vec<f32> = (0..colors.len()).map(|i| (i % 5) as f32).collect();
+        let repeated: Vec<Vec3> = colors
+            .iter()
+            .zip(weights.iter())
+            .flat_map(|(color, &weight)| std::iter::repeat_n(*color, weight as usize))
+            .collect();
+        let provided: Vec<Vec4> = repeated
+            .iter()
+            .step_by(40)
+            .map(|c| [c[0], c[1], c[2], 0.0])
+            .collect();
+
+        for algorithm in [KMeansAlgorithm::Lloyd, KMeansAlgorithm::Hamerly] {
+            let kmeans = KMeansCPU::default()
+                .with_k(provided.len())
+                .with_tolerance(1e-6)
+                .with_max_iterations(500)
+                .with_algorithm(algorithm)
+                .with_initializer(Initializer::Provided(provided.clone()));
+
+            let (_, expected) = kmeans.run(&repeated).unwrap();
+            let (clusters, centroids) = kmeans.run_weighted(&colors, &weights).unwrap();
+
+            centroids.assert_almost_eq(&expected, 1e-2);
+            // Zero weight colors are still assigned
+            assert_eq!(clusters.len(), colors.len());
+            assert_eq!(clusters[0], find_closest_centroid(&colors[0], &centroids));
+        }
+    }
+
+    #[test]
+    fn test_zero_weight_clusters_keep_their_centroid() {
+        let data: Vec<Vec3> = vec![[0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [250.0, 250.0, 250.0]];
+        let weights = [1.0, 1.0, 0.0];
+        let config = KMeansConfig {
+            k: 2,
+            initializer: Initializer::Provided(vec![
+                [0.0, 0.0, 0.0, 0.0],
+                [255.0, 255.0, 255.0, 0.0],
+            ]),
+            ..Default::default()
+        };
+
+        let (_, lloyd) = lloyd::kmeans_lloyd_weighted(&data, &weights, &config);
+        let (_, hamerly) = hamerly::kmeans_hamerly_weighted(&data, &weights, &config);
+
+        for centroids in [lloyd, hamerly] {
+            assert_eq!(centroids[0], [5.0, 0.0, 0.0]);
+            assert_eq!(centroids[1], [255.0, 255.0, 255.0]);
+        }
     }
 }
@@ -0,0 +1,234 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::hamerly::{kmeans_hamerly, kmeans_hamerly_from_centroids};
+use crate::kmeans::types::{Assignments, Centroids};
+use crate::kmeans::utils::{bic, drop_empty_clusters, split_config};
+use crate::types::VectorExt;
+
+// Critical value of the Anderson-Darling statistic at a significance level of 0.0001,
+// the level used in the G-means paper
+const ANDERSON_DARLING_CRITICAL_VALUE: f64 = 1.8692;
+// The tests need a handful of points to say anything, so smaller clusters are kept whole
+const MIN_SPLIT_SIZE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitTest {
+    // X-means (Pelleg & Moore, 2000): split when two children have a better BIC than the parent
+    Bic,
+    // G-means (Hamerly & Elkan, 2003): split when the cluster, projected onto the axis
+    // joining its children, fails an Anderson-Darling test for normality
+    AndersonDarling,
+}
+
+// Grows the palette from a single color. Every round refines all centroids with
+// Hamerly's algorithm, then tries a local 2-means on each cluster and keeps the splits
+// the test accepts, strongest evidence first, until no cluster splits or there are k.
+// The result can have fewer than k colors.
+pub fn kmeans_adaptive<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+    split_test: SplitTest,
+) -> (Assignments, Centroids<T>) {
+    if data.is_empty() || config.k == 0 {
+        return (vec![0; data.len()], Vec::new());
+    }
+
+    let mean = data
+        .iter()
+        .fold(T::zero(), |sum, pixel| sum.add(pixel))
+        .div_scalar(data.len() as f32);
+    let mut centroids = vec![mean];
+
+    // Every round adds at least one centroid, so this bounds the number of rounds
+    for round in 0..config.k {
+        let (assignments, refined) = kmeans_hamerly_from_centroids(data, config, centroids);
+        let (assignments, refined) = drop_empty_clusters(assignments, refined);
+
+        let mut members = vec![Vec::new(); refined.len()];
+        for (pixel, &cluster) in data.iter().zip(assignments.iter()) {
+            members[cluster].push(*pixel);
+        }
+
+        let mut splits = Vec::new();
+        if refined.len() < config.k {
+            for (cluster, points) in members.iter().enumerate() {
+                let split_config = split_config(config, round * config.k + cluster);
+                if let Some((score, children)) =
+                    try_split(points, &refined[cluster], &split_config, split_test)
+                {
+                    splits.push((score, cluster, children));
+                }
+            }
+        }
+
+        if splits.is_empty() {
+            return (assignments, refined);
+        }
+
+        splits.sort_by(|a, b| b.0.total_cmp(&a.0));
+        splits.truncate(config.k - refined.len());
+
+        centroids = refined;
+        for (_, cluster, [left, right]) in splits {
+            centroids[cluster] = left;
+            centroids.push(right);
+        }
+    }
+
+    let (assignments, centroids) = kmeans_hamerly_from_centroids(data, config, centroids);
+    drop_empty_clusters(assignments, centroids)
+}
+
+// Returns the children and a score for how strongly the test favours the split
+fn try_split<T: VectorExt>(
+    points: &[T],
+    parent: &T,
+    split_config: &KMeansConfig,
+    split_test: SplitTest,
+) -> Option<(f64, [T; 2])> {
+    if points.len() < MIN_SPLIT_SIZE {
+        return None;
+    }
+    let first = &points[0];
+    if points
+        .iter()
+        .all(|pixel| (0..3).all(|channel| pixel[channel] == first[channel]))
+    {
+        return None;
+    }
+
+    let (assignments, children) = kmeans_hamerly(points, split_config);
+    let left_count = assignments.iter().filter(|&&cluster| cluster == 0).count();
+    if left_count == 0 || left_count == points.len() {
+        return None;
+    }
+
+    let score = match split_test {
+        SplitTest::Bic => {
+            let parent_bic = bic(points, &vec![0; points.len()], &[*parent]);
+            let children_bic = bic(points, &assignments, &children);
+            children_bic - parent_bic
+        }
+        SplitTest::AndersonDarling => {
+            let axis: Vec<f64> = (0..3)
+                .map(|channel| (children[0][channel] - children[1][channel]) as f64)
+                .collect();
+            let projections = points
+                .iter()
+                .map(|pixel| {
+                    (0..3)
+                        .map(|channel| pixel[channel] as f64 * axis[channel])
+                        .sum()
+                })
+                .collect();
+            anderson_darling(projections) - ANDERSON_DARLING_CRITICAL_VALUE
+        }
+    };
+
+    (score > 0.0).then(|| (score, [children[0], children[1]]))
+}
+
+// Anderson-Darling statistic for normality with estimated mean and variance,
+// including the small sample correction (Stephens, 1974)
+fn anderson_darling(mut values: Vec<f64>) -> f64 {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    if variance <= 0.0 {
+        return 0.0;
+    }
+
+    let std_dev = variance.sqrt();
+    values.sort_by(f64::total_cmp);
+    let cdf: Vec<f64> = values
+        .iter()
+        .map(|x| normal_cdf((x - mean) / std_dev).clamp(1e-12, 1.0 - 1e-12))
+        .collect();
+
+    let sum: f64 = (0..cdf.len())
+        .map(|i| (2 * i + 1) as f64 * (cdf[i].ln() + (1.0 - cdf[cdf.len() - 1 - i]).ln()))
+        .sum();
+    let a2 = -n - sum / n;
+    a2 * (1.0 + 4.0 / n - 25.0 / (n * n))
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+// Abramowitz & Stegun 7.1.26, accurate to 1.5e-7
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let y = 1.0 - polynomial * (-x * x).exp();
+    y.copysign(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kmeans::initializer::Initializer;
+    use crate::kmeans::utils::gaussian_blobs;
+    use crate::types::Vec3;
+
+    // Four well separated gaussian blobs
+    fn four_blobs() -> Vec<Vec3> {
+        let centers = [
+            [40.0, 40.0, 40.0],
+            [200.0, 60.0, 60.0],
+            [60.0, 200.0, 120.0],
+            [210.0, 210.0, 220.0],
+        ];
+        gaussian_blobs(&centers, 2000, 10.0, 9)
+    }
+
+    #[test]
+    fn test_both_tests_stop_at_the_number_of_blobs() {
+        let data = four_blobs();
+        for split_test in [SplitTest::Bic, SplitTest::AndersonDarling] {
+            let config = KMeansConfig {
+                k: 32,
+                seed: Some(1),
+                ..Default::default()
+            };
+
+            let (assignments, centroids) = kmeans_adaptive(&data, &config, split_test);
+
+            assert_eq!(centroids.len(), 4, "{:?}", split_test);
+            assert!(assignments.iter().all(|&cluster| cluster < 4));
+        }
+    }
+
+    #[test]
+    fn test_palette_never_exceeds_k() {
+        let data = four_blobs();
+        let config = KMeansConfig {
+            k: 3,
+            seed: Some(1),
+            ..Default::default()
+        };
+
+        let (_, centroids) = kmeans_adaptive(&data, &config, SplitTest::AndersonDarling);
+        assert_eq!(centroids.len(), 3);
+    }
+
+    #[test]
+    fn test_splits_ignore_provided_centroids() {
+        let data = four_blobs();
+        let config = KMeansConfig {
+            k: 8,
+            seed: Some(1),
+            ..Default::default()
+        };
+        let provided = KMeansConfig {
+            initializer: Initializer::Provided(vec![[40.0, 40.0, 40.0, 255.0]; 2]),
+            ..config.clone()
+        };
+
+        assert_eq!(
+            kmeans_adaptive(&data, &config, SplitTest::Bic),
+            kmeans_adaptive(&data, &provided, SplitTest::Bic)
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use crate::kmeans::distance::euclidean_distance_squared;
+use crate::kmeans::initializer::get_seedable_rng;
+use crate::kmeans::types::KMeansError;
+use crate::kmeans::utils::{bic, inertia, num_distinct_colors};
+use crate::kmeans::KMeansCPU;
+use crate::types::VectorExt;
+use rand::seq::index::sample;
+use std::ops::RangeInclusive;
+
+pub const DEFAULT_SILHOUETTE_SAMPLE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum KSelectionCriterion {
+    // Knee of the inertia curve
+    Elbow,
+    // Highest mean silhouette, measured on a random sample of the points
+    Silhouette { sample_size: usize },
+    // Highest Bayesian information criterion
+    Bic,
+}
+
+#[derive(Debug, Clone)]
+pub struct KSelection {
+    pub k: usize,
+    // The score of every k that was tried, in increasing order of k.
+    // Scores are the inertia for Elbow, the mean silhouette for Silhouette and the BIC for Bic.
+    pub scores: Vec<(usize, f32)>,
+}
+
+// Runs k-means for every k in the range and picks one by the given criterion.
+// The range is capped at the number of distinct colors in the data.
+pub fn select_k<T: VectorExt>(
+    data: &[T],
+    kmeans: &KMeansCPU,
+    ks: RangeInclusive<usize>,
+    criterion: KSelectionCriterion,
+) -> Result<KSelection, KMeansError> {
+    let min_k = (*ks.start()).max(1);
+    let unique_colors = num_distinct_colors(data);
+    let max_k = (*ks.end()).min(unique_colors);
+    if min_k > max_k {
+        return Err(KMeansError(format!(
+            "No k in {:?} fits data with {} unique colors",
+            ks, unique_colors
+        )));
+    }
+
+    // Score every k against the same sample so that the curve is comparable
+    let silhouette_sample = match criterion {
+        KSelectionCriterion::Silhouette { sample_size } => {
+            let mut rng = get_seedable_rng(kmeans.config().seed);
+            sample(&mut rng, data.len(), sample_size.min(data.len())).into_vec()
+        }
+        _ => Vec::new(),
+    };
+
+    let mut scores = Vec::with_capacity(max_k - min_k + 1);
+    for k in min_k..=max_k {
+        let (assignments, centroids) = kmeans.clone().with_k(k).run(data)?;
+        let score = match criterion {
+            KSelectionCriterion::Elbow => inertia(data, &assignments, &centroids).0,
+            KSelectionCriterion::Silhouette { .. } => {
+                mean_silhouette(data, &assignments, k, &silhouette_sample)
+            }
+            KSelectionCriterion::Bic => bic(data, &assignments, &centroids) as f32,
+        };
+        scores.push((k, score));
+    }
+
+    let k = match criterion {
+        KSelectionCriterion::Elbow => elbow(&scores),
+        KSelectionCriterion::Silhouette { .. } | KSelectionCriterion::Bic => {
+            // The first k wins ties, so that flat curves favour smaller palettes
+            scores
+                .iter()
+                .rev()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap()
+                .0
+        }
+    };
+
+    Ok(KSelection { k, scores })
+}
+
+// The point of the curve farthest below the line joining its endpoints (Satopaa et al., 2011)
+fn elbow(scores: &[(usize, f32)]) -> usize {
+    let (first_k, first_score) = scores[0];
+    let (last_k, last_score) = scores[scores.len() - 1];
+    if first_k == last_k {
+        return first_k;
+    }
+
+    let mut best = (first_k, 0.0);
+    for &(k, score) in scores {
+        let t = (k - first_k) as f32 / (last_k - first_k) as f32;
+        let chord = first_score + t * (last_score - first_score);
+        // Normalize by the total drop so that the threshold is independent of scale
+        let gap = (chord - score) / (first_score - last_score).max(f32::EPSILON);
+        if gap > best.1 {
+            best = (k, gap);
+        }
+    }
+    best.0
+}
+
+// Mean silhouette coefficient (Rousseeuw, 1987) of the sampled points, measured
+// against the other sampled points
+fn mean_silhouette<T: VectorExt>(
+    data: &[T],
+    assignments: &[usize],
+    k: usize,
+    sample: &[usize],
+) -> f32 {
+    if k < 2 || sample.is_empty() {
+        return 0.0;
+    }
+
+    let mut total = 0.0;
+    let mut distance_sums = vec![0.0; k];
+    let mut counts = vec![0usize; k];
+    for &i in sample {
+        distance_sums.fill(0.0);
+        counts.fill(0);
+        for &j in sample {
+            if i != j {
+                let cluster = assignments[j];
+                distance_sums[cluster] += euclidean_distance_squared(&data[i], &data[j]).sqrt().0;
+                counts[cluster] += 1;
+            }
+        }
+
+        let own = assignments[i];
+        // Points alone in their cluster score 0
+        if counts[own] == 0 {
+            continue;
+        }
+        let a = distance_sums[own] / counts[own] as f32;
+        let b = (0..k)
+            .filter(|&cluster| cluster != own && counts[cluster] > 0)
+            .map(|cluster| distance_sums[cluster] / counts[cluster] as f32)
+            .fold(f32::MAX, f32::min);
+        if b == f32::MAX {
+            continue;
+        }
+        total += (b - a) / a.max(b).max(f32::EPSILON);
+    }
+    total / sample.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kmeans::utils::gaussian_blobs;
+    use crate::types::Vec3;
+
+    // Three well separated gaussian blobs
+    fn three_blobs() -> Vec<Vec3> {
+        let centers = [
+            [40.0, 40.0, 40.0],
+            [128.0, 200.0, 90.0],
+            [220.0, 60.0, 200.0],
+        ];
+        gaussian_blobs(&centers, 600, 8.0, 5)
+    }
+
+    #[test]
+    fn test_every_criterion_finds_three_blobs() {
+        let data = three_blobs();
+        let kmeans = KMeansCPU::default().with_seed(11);
+
+        for criterion in [
+            KSelectionCriterion::Elbow,
+            KSelectionCriterion::Silhouette { sample_size: 200 },
+            KSelectionCriterion::Bic,
+        ] {
+            let selection = select_k(&data, &kmeans, 1..=8, criterion).unwrap();
+            assert_eq!(selection.k, 3, "{:?}: {:?}", criterion, selection.scores);
+            assert_eq!(
+                selection.scores.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+                (1..=8).collect::<Vec<_>>()
+            );
+        }
+    }
+}
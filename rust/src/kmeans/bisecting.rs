@@ -0,0 +1,168 @@
+use crate::kmeans::config::{KMeansAlgorithm, KMeansConfig};
+use crate::kmeans::distance::{euclidean_distance_squared, SquaredEuclideanDistance};
+use crate::kmeans::lloyd::kmeans_lloyd;
+use crate::kmeans::types::{Assignments, Centroids};
+use crate::kmeans::utils::split_config;
+use crate::types::VectorExt;
+
+struct Cluster<T> {
+    indices: Vec<usize>,
+    centroid: T,
+    sse: SquaredEuclideanDistance,
+}
+
+impl<T: VectorExt> Cluster<T> {
+    fn new(data: &[T], indices: Vec<usize>) -> Self {
+        let centroid = indices
+            .iter()
+            .fold(T::zero(), |sum, &idx| sum.add(&data[idx]))
+            .div_scalar(indices.len() as f32);
+        let sse = indices
+            .iter()
+            .map(|&idx| euclidean_distance_squared(&data[idx], &centroid))
+            .sum();
+        Cluster {
+            indices,
+            centroid,
+            sse,
+        }
+    }
+}
+
+// Bisecting k-means: start with one cluster and repeatedly split the cluster with
+// the highest SSE in two with 2-means, until there are k clusters.
+// Each split replaces one palette entry and appends another, so the palette at
+// every level is a refinement of the one before it.
+//
+// Returns the final assignments and the palette at every level, where level i has
+// i + 1 colors. There are fewer than k levels if the data runs out of distinct colors.
+pub fn bisecting_hierarchy<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+) -> (Assignments, Vec<Centroids<T>>) {
+    if data.is_empty() || config.k == 0 {
+        return (vec![0; data.len()], Vec::new());
+    }
+
+    let mut clusters = vec![Cluster::new(data, (0..data.len()).collect())];
+    let mut levels = vec![vec![clusters[0].centroid]];
+    // Clusters that 2-means failed to split are never tried again
+    let mut unsplittable = vec![false];
+
+    let mut subset = Vec::new();
+    while clusters.len() < config.k {
+        let Some(target) = (0..clusters.len())
+            .filter(|&i| !unsplittable[i] && clusters[i].sse.0 > 0.0)
+            .max_by(|&a, &b| clusters[a].sse.partial_cmp(&clusters[b].sse).unwrap())
+        else {
+            break; // every cluster is a single color
+        };
+
+        subset.clear();
+        subset.extend(clusters[target].indices.iter().map(|&idx| data[idx]));
+
+        let split_config = KMeansConfig {
+            algorithm: KMeansAlgorithm::Lloyd,
+            ..split_config(config, levels.len())
+        };
+        let (assignments, _) = kmeans_lloyd(&subset, &split_config);
+
+        let (left, right): (Vec<_>, Vec<_>) = clusters[target]
+            .indices
+            .iter()
+            .zip(assignments.iter())
+            .partition(|(_, &cluster)| cluster == 0);
+        if left.is_empty() || right.is_empty() {
+            unsplittable[target] = true;
+            continue;
+        }
+
+        let left = left.into_iter().map(|(&idx, _)| idx).collect();
+        let right = right.into_iter().map(|(&idx, _)| idx).collect();
+        clusters[target] = Cluster::new(data, left);
+        clusters.push(Cluster::new(data, right));
+        unsplittable.push(false);
+
+        levels.push(clusters.iter().map(|cluster| cluster.centroid).collect());
+    }
+
+    let mut assignments = vec![0; data.len()];
+    for (i, cluster) in clusters.iter().enumerate() {
+        for &idx in &cluster.indices {
+            assignments[idx] = i;
+        }
+    }
+
+    (assignments, levels)
+}
+
+pub fn kmeans_bisecting<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+) -> (Assignments, Centroids<T>) {
+    let (assignments, mut levels) = bisecting_hierarchy(data, config);
+    (assignments, levels.pop().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kmeans::initializer::Initializer;
+    use crate::types::Vec3;
+
+    #[test]
+    fn test_bisecting_levels_are_nested() {
+        let data: Vec<Vec3> = (0..400)
+            .map(|i| {
+                let base = (i % 8) as f32 * 30.0;
+                let jitter = (i % 5) as f32;
+                [base + jitter, 255.0 - base, base / 2.0 + jitter]
+            })
+            .collect();
+        let config = KMeansConfig {
+            k: 8,
+            seed: Some(3),
+            ..Default::default()
+        };
+
+        let (assignments, levels) = bisecting_hierarchy(&data, &config);
+
+        assert_eq!(levels.len(), 8);
+        for (i, level) in levels.iter().enumerate() {
+            assert_eq!(level.len(), i + 1);
+        }
+        // Every split keeps all but one of the parent level's colors
+        for pair in levels.windows(2) {
+            let kept = pair[0]
+                .iter()
+                .filter(|color| pair[1].contains(color))
+                .count();
+            assert_eq!(kept, pair[0].len() - 1);
+        }
+        assert!(assignments.iter().all(|&cluster| cluster < 8));
+    }
+
+    #[test]
+    fn test_splits_ignore_provided_centroids() {
+        let data: Vec<Vec3> = (0..200)
+            .map(|i| {
+                let base = (i % 4) as f32 * 60.0;
+                [base, 255.0 - base, (i % 3) as f32]
+            })
+            .collect();
+        let config = KMeansConfig {
+            k: 4,
+            seed: Some(2),
+            ..Default::default()
+        };
+        let provided = KMeansConfig {
+            initializer: Initializer::Provided(vec![[0.0, 0.0, 0.0, 255.0]; 2]),
+            ..config.clone()
+        };
+
+        assert_eq!(
+            bisecting_hierarchy(&data, &config),
+            bisecting_hierarchy(&data, &provided)
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::kmeans::adaptive::SplitTest;
+use crate::kmeans::gmm::CovarianceType;
 #[cfg(feature = "gpu")]
 use crate::kmeans::gpu::GpuAlgorithm;
 use crate::kmeans::initializer::Initializer;
@@ -7,6 +9,31 @@ use std::fmt;
 pub enum KMeansAlgorithm {
     Lloyd,
     Hamerly,
+    Elkan,
+    Yinyang,
+    MiniBatch {
+        batch_size: usize,
+    },
+    MedianCut,
+    Wu,
+    Octree,
+    NeuQuant {
+        sample_factor: usize,
+    },
+    Bisecting,
+    Pnn,
+    // Grows the palette up to k colors, splitting clusters that fail the test
+    Adaptive {
+        split_test: SplitTest,
+    },
+    FuzzyCMeans {
+        fuzzifier: f32,
+    },
+    GaussianMixture {
+        covariance: CovarianceType,
+    },
+    // Every centroid is one of the input pixels
+    KMedoids,
     #[cfg(feature = "gpu")]
     Gpu(GpuAlgorithm),
 }
@@ -21,12 +48,54 @@ impl KMeansAlgorithm {
     }
 }
 
+impl KMeansAlgorithm {
+    // Whether the result depends only on the data, so that restarts would repeat it
+    pub fn is_deterministic(&self) -> bool {
+        matches!(
+            self,
+            KMeansAlgorithm::MedianCut
+                | KMeansAlgorithm::Wu
+                | KMeansAlgorithm::Octree
+                | KMeansAlgorithm::Pnn
+                | KMeansAlgorithm::NeuQuant { .. }
+        )
+    }
+
+    // Whether the initial centroids are the only thing the seed changes
+    fn is_seeded_by_initializer(&self) -> bool {
+        match self {
+            KMeansAlgorithm::Lloyd
+            | KMeansAlgorithm::Hamerly
+            | KMeansAlgorithm::Elkan
+            | KMeansAlgorithm::Yinyang => true,
+            #[cfg(feature = "gpu")]
+            KMeansAlgorithm::Gpu(_) => true,
+            _ => false,
+        }
+    }
+}
+
 impl fmt::Display for KMeansAlgorithm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{self:?}")
     }
 }
 
+// What to do with a cluster that loses all of its pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyClusterPolicy {
+    // Leave the centroid where it is, which is how Lloyd has always behaved. It can
+    // leave palette colors that no pixel maps to.
+    #[default]
+    Keep,
+    // Move the centroid onto the pixel farthest from its own centroid
+    ReseedFarthest,
+    // Give the centroid half of the largest cluster, cut across its widest channel
+    SplitLargest,
+    // Remove clusters that are still empty at the end, which leaves fewer than k colors
+    Drop,
+}
+
 #[derive(Debug, Clone)]
 pub struct KMeansConfig {
     pub k: usize,
@@ -35,6 +104,9 @@ pub struct KMeansConfig {
     pub algorithm: KMeansAlgorithm,
     pub initializer: Initializer,
     pub seed: Option<u64>,
+    // Number of restarts, each seeded differently. The one with the lowest inertia wins.
+    pub n_init: usize,
+    pub empty_cluster_policy: EmptyClusterPolicy,
 }
 
 impl Default for KMeansConfig {
@@ -46,6 +118,21 @@ impl Default for KMeansConfig {
             algorithm: KMeansAlgorithm::Lloyd,
             initializer: Initializer::KMeansPlusPlus,
             seed: None,
+            n_init: 1,
+            empty_cluster_policy: EmptyClusterPolicy::default(),
+        }
+    }
+}
+
+impl KMeansConfig {
+    // How many runs n_init asks for that can differ. Deterministic algorithms, and
+    // Lloyd-style algorithms started from a seedless initializer, repeat the same run.
+    pub fn restarts(&self) -> usize {
+        let seedless = self.initializer.is_seedless() && self.algorithm.is_seeded_by_initializer();
+        if self.algorithm.is_deterministic() || seedless {
+            1
+        } else {
+            self.n_init.max(1)
         }
     }
 }
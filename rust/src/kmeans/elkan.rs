@@ -0,0 +1,222 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::distance::{euclidean_distance_squared, EuclideanDistance};
+use crate::kmeans::types::{Assignments, CentroidCounts, CentroidSums, Centroids};
+use crate::kmeans::utils::{
+    cluster_sums, fill_empty_clusters, finish_empty_clusters, has_converged, move_centroids,
+    reset_moved_bounds,
+};
+use crate::types::VectorExt;
+use itertools::izip;
+
+type UpperBounds = Vec<EuclideanDistance>;
+// One lower bound per (pixel, centroid) pair, stored row-major by pixel.
+type LowerBounds = Vec<EuclideanDistance>;
+
+pub fn kmeans_elkan<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+) -> (Assignments, Centroids<T>) {
+    let (
+        mut centroids,
+        mut centroid_sums,
+        mut centroid_counts,
+        mut upper_bounds,
+        mut lower_bounds,
+        mut clusters,
+    ) = initialize_elkan(data, config);
+
+    let k = config.k;
+    let mut centroid_distances = vec![EuclideanDistance(0.0); k * k];
+    let mut centroid_neighbor_distances = vec![EuclideanDistance(f32::MAX); k];
+    let mut centroid_move_distances = vec![EuclideanDistance(0.0); k];
+    let mut new_centroids = centroids.clone();
+
+    assert!(data.len() >= k);
+
+    for _ in 0..config.max_iterations {
+        compute_centroid_distances(
+            &centroids,
+            &mut centroid_distances,
+            &mut centroid_neighbor_distances,
+        );
+
+        for (pixel, assigned_cluster, upper_bound, lower_bound) in izip!(
+            data,
+            &mut clusters,
+            &mut upper_bounds,
+            lower_bounds.chunks_exact_mut(k)
+        ) {
+            // No other centroid can be closer than half the distance to the nearest neighbor
+            if *upper_bound <= centroid_neighbor_distances[*assigned_cluster] / (2.).into() {
+                continue;
+            }
+
+            let mut best_index = *assigned_cluster;
+            // The upper bound is loose until we recompute it for this iteration
+            let mut upper_bound_is_tight = false;
+
+            for j in 0..k {
+                if j == best_index
+                    || *upper_bound <= lower_bound[j]
+                    || *upper_bound <= centroid_distances[best_index * k + j] / (2.).into()
+                {
+                    continue;
+                }
+
+                if !upper_bound_is_tight {
+                    *upper_bound = euclidean_distance_squared(pixel, &centroids[best_index]).sqrt();
+                    lower_bound[best_index] = *upper_bound;
+                    upper_bound_is_tight = true;
+
+                    if *upper_bound <= lower_bound[j]
+                        || *upper_bound <= centroid_distances[best_index * k + j] / (2.).into()
+                    {
+                        continue;
+                    }
+                }
+
+                let distance = euclidean_distance_squared(pixel, &centroids[j]).sqrt();
+                lower_bound[j] = distance;
+                if distance < *upper_bound {
+                    *upper_bound = distance;
+                    best_index = j;
+                }
+            }
+
+            if best_index != *assigned_cluster {
+                centroid_sums[*assigned_cluster] = centroid_sums[*assigned_cluster].sub(pixel);
+                centroid_counts[*assigned_cluster] -= 1;
+                centroid_sums[best_index] = centroid_sums[best_index].add(pixel);
+                centroid_counts[best_index] += 1;
+
+                *assigned_cluster = best_index;
+            }
+        }
+
+        if centroid_counts.contains(&0) {
+            let moved =
+                fill_empty_clusters(data, &mut clusters, &centroids, config.empty_cluster_policy);
+            if !moved.is_empty() {
+                (centroid_sums, centroid_counts) = cluster_sums(data, &clusters, k);
+                reset_moved_bounds(&moved, &mut upper_bounds, &mut lower_bounds);
+            }
+        }
+
+        move_centroids(
+            &centroids,
+            &mut new_centroids,
+            &centroid_sums,
+            |j| centroid_counts[j] as f32,
+            &mut centroid_move_distances,
+        );
+
+        if has_converged(&centroids, &new_centroids, config.tolerance) {
+            std::mem::swap(&mut centroids, &mut new_centroids);
+            break;
+        }
+        std::mem::swap(&mut centroids, &mut new_centroids);
+
+        update_bounds(
+            &mut upper_bounds,
+            &mut lower_bounds,
+            &centroid_move_distances,
+            &clusters,
+        );
+    }
+    finish_empty_clusters(clusters, centroids, config.empty_cluster_policy)
+}
+
+fn initialize_elkan<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+) -> (
+    Centroids<T>,
+    CentroidSums<T>,
+    CentroidCounts,
+    UpperBounds,
+    LowerBounds,
+    Assignments,
+) {
+    let centroids = config
+        .initializer
+        .initialize_centroids(data, config.k, config.seed);
+
+    let k = config.k;
+    let num_pixels = data.len();
+    let mut clusters = vec![0; num_pixels];
+    let mut upper_bounds = vec![EuclideanDistance(0.0); num_pixels];
+    let mut lower_bounds = vec![EuclideanDistance(0.0); num_pixels * k];
+
+    let mut centroid_sums = vec![T::zero(); k];
+    let mut centroid_counts = vec![0; k];
+
+    for (pixel, cluster, upper_bound, lower_bound) in izip!(
+        data,
+        &mut clusters,
+        &mut upper_bounds,
+        lower_bounds.chunks_exact_mut(k)
+    ) {
+        let mut best_distance = EuclideanDistance(f32::MAX);
+        let mut best_index = 0;
+        for (j, centroid) in centroids.iter().enumerate() {
+            let distance = euclidean_distance_squared(pixel, centroid).sqrt();
+            lower_bound[j] = distance;
+            if distance < best_distance {
+                best_distance = distance;
+                best_index = j;
+            }
+        }
+
+        *upper_bound = best_distance;
+        *cluster = best_index;
+        centroid_sums[best_index] = centroid_sums[best_index].add(pixel);
+        centroid_counts[best_index] += 1;
+    }
+
+    (
+        centroids,
+        centroid_sums,
+        centroid_counts,
+        upper_bounds,
+        lower_bounds,
+        clusters,
+    )
+}
+
+fn compute_centroid_distances<T: VectorExt>(
+    centroids: &[T],
+    distances: &mut [EuclideanDistance],
+    neighbor_distances: &mut [EuclideanDistance],
+) {
+    let k = centroids.len();
+    neighbor_distances.fill(EuclideanDistance(f32::MAX));
+
+    for i in 0..k {
+        distances[i * k + i] = EuclideanDistance(0.0);
+        for j in (i + 1)..k {
+            let distance = euclidean_distance_squared(&centroids[i], &centroids[j]).sqrt();
+            distances[i * k + j] = distance;
+            distances[j * k + i] = distance;
+            neighbor_distances[i] = neighbor_distances[i].min(distance);
+            neighbor_distances[j] = neighbor_distances[j].min(distance);
+        }
+    }
+}
+
+#[inline]
+fn update_bounds(
+    upper_bounds: &mut [EuclideanDistance],
+    lower_bounds: &mut [EuclideanDistance],
+    distances: &[EuclideanDistance],
+    clusters: &[usize],
+) {
+    let k = distances.len();
+    for (upper_bound, lower_bound, &cluster) in
+        izip!(upper_bounds, lower_bounds.chunks_exact_mut(k), clusters)
+    {
+        *upper_bound += distances[cluster];
+        for (bound, &distance) in lower_bound.iter_mut().zip(distances) {
+            *bound = (*bound - distance).max_f32(0.0);
+        }
+    }
+}
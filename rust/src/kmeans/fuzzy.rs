@@ -0,0 +1,213 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::distance::euclidean_distance_squared;
+use crate::kmeans::types::{Assignments, Centroids};
+use crate::kmeans::utils::has_converged;
+use crate::types::VectorExt;
+
+pub const DEFAULT_FUZZIFIER: f32 = 2.0;
+// Memberships degenerate into hard assignments as the fuzzifier approaches 1
+const MIN_FUZZIFIER: f32 = 1.01;
+
+// Degree to which every pixel belongs to every cluster, stored row-major (pixels × k).
+// Each row sums to 1.
+#[derive(Debug, Clone)]
+pub struct Memberships {
+    k: usize,
+    values: Vec<f32>,
+}
+
+impl Memberships {
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    // Number of pixels
+    pub fn len(&self) -> usize {
+        self.values.len() / self.k.max(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn row(&self, pixel: usize) -> &[f32] {
+        &self.values[pixel * self.k..(pixel + 1) * self.k]
+    }
+
+    // The cluster each pixel belongs to most
+    pub fn hard_assignments(&self) -> Assignments {
+        self.values
+            .chunks_exact(self.k)
+            .map(|row| {
+                row.iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.total_cmp(b.1))
+                    .map_or(0, |(cluster, _)| cluster)
+            })
+            .collect()
+    }
+}
+
+// Fills `row` with the membership of the pixel in each centroid's cluster
+pub fn memberships<T: VectorExt>(pixel: &T, centroids: &[T], fuzzifier: f32, row: &mut [f32]) {
+    let exponent = 1.0 / (fuzzifier.max(MIN_FUZZIFIER) - 1.0);
+
+    let mut nearest = f32::MAX;
+    for (membership, centroid) in row.iter_mut().zip(centroids.iter()) {
+        *membership = euclidean_distance_squared(pixel, centroid).0;
+        nearest = nearest.min(*membership);
+    }
+
+    // A pixel sitting on a centroid belongs to it alone
+    if nearest == 0.0 {
+        let cluster = row.iter().position(|&distance| distance == 0.0).unwrap();
+        row.fill(0.0);
+        row[cluster] = 1.0;
+        return;
+    }
+
+    // Scale by the nearest distance so that large exponents can't underflow
+    let mut total = 0.0;
+    for membership in row.iter_mut() {
+        *membership = (nearest / *membership).powf(exponent);
+        total += *membership;
+    }
+    for membership in row.iter_mut() {
+        *membership /= total;
+    }
+}
+
+// Mixes the `top_n` colors the row belongs to most, weighted by membership
+pub fn blend<T: VectorExt>(row: &[f32], centroids: &[T], top_n: usize) -> T {
+    let mut ranked: Vec<usize> = (0..row.len()).collect();
+    let top_n = top_n.clamp(1, row.len());
+    if top_n < row.len() {
+        ranked.select_nth_unstable_by(top_n - 1, |&a, &b| row[b].total_cmp(&row[a]));
+    }
+
+    let top = &ranked[..top_n];
+    let total: f32 = top.iter().map(|&cluster| row[cluster]).sum();
+    top.iter()
+        .fold(T::zero(), |color, &cluster| {
+            color.add(&centroids[cluster].mul_scalar(row[cluster]))
+        })
+        .div_scalar(total)
+}
+
+// Fuzzy c-means (Bezdek, 1981). Every pixel belongs to every cluster to a degree that
+// falls off with distance, and centroids are means weighted by membership^fuzzifier.
+// Larger fuzzifiers give softer memberships.
+pub fn fuzzy_cmeans<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+    fuzzifier: f32,
+) -> (Memberships, Centroids<T>) {
+    let k = config.k;
+    let mut centroids = config
+        .initializer
+        .initialize_centroids(data, k, config.seed);
+    let mut values = vec![0.0; data.len() * k];
+    if k == 0 {
+        return (Memberships { k, values }, centroids);
+    }
+
+    let fuzzifier = fuzzifier.max(MIN_FUZZIFIER);
+    let mut sums = vec![T::zero(); k];
+    let mut weights = vec![0.0f32; k];
+    for _ in 0..config.max_iterations {
+        sums.fill(T::zero());
+        weights.fill(0.0);
+        for (pixel, row) in data.iter().zip(values.chunks_exact_mut(k)) {
+            memberships(pixel, &centroids, fuzzifier, row);
+            for (cluster, &membership) in row.iter().enumerate() {
+                let weight = membership.powf(fuzzifier);
+                sums[cluster] = sums[cluster].add(&pixel.mul_scalar(weight));
+                weights[cluster] += weight;
+            }
+        }
+
+        let new_centroids: Centroids<T> = centroids
+            .iter()
+            .enumerate()
+            .map(|(cluster, centroid)| {
+                if weights[cluster] > 0.0 {
+                    sums[cluster].div_scalar(weights[cluster])
+                } else {
+                    *centroid
+                }
+            })
+            .collect();
+
+        let converged = has_converged(&centroids, &new_centroids, config.tolerance);
+        centroids = new_centroids;
+        if converged {
+            break;
+        }
+    }
+
+    // Memberships for the centroids we return
+    for (pixel, row) in data.iter().zip(values.chunks_exact_mut(k)) {
+        memberships(pixel, &centroids, fuzzifier, row);
+    }
+
+    (Memberships { k, values }, centroids)
+}
+
+pub fn kmeans_fuzzy<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+    fuzzifier: f32,
+) -> (Assignments, Centroids<T>) {
+    let (memberships, centroids) = fuzzy_cmeans(data, config, fuzzifier);
+    (memberships.hard_assignments(), centroids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec3;
+
+    #[test]
+    fn test_memberships_sum_to_one_and_favour_nearest() {
+        let data: Vec<Vec3> = (0..200)
+            .map(|i| {
+                let t = (i % 100) as f32 * 2.55;
+                if i < 100 {
+                    [t, 0.0, 0.0]
+                } else {
+                    [0.0, t, 255.0]
+                }
+            })
+            .collect();
+        let config = KMeansConfig {
+            k: 4,
+            seed: Some(2),
+            ..Default::default()
+        };
+
+        let (memberships, centroids) = fuzzy_cmeans(&data, &config, DEFAULT_FUZZIFIER);
+        assert_eq!(memberships.len(), data.len());
+        assert_eq!(memberships.k(), 4);
+
+        let assignments = memberships.hard_assignments();
+        for (i, pixel) in data.iter().enumerate() {
+            let row = memberships.row(i);
+            assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+            assert_eq!(
+                assignments[i],
+                crate::kmeans::find_closest_centroid(pixel, &centroids)
+            );
+        }
+    }
+
+    #[test]
+    fn test_blend_mixes_top_colors() {
+        let centroids: Vec<Vec3> = vec![[0.0, 0.0, 0.0], [100.0, 0.0, 0.0], [0.0, 0.0, 200.0]];
+        let row = [0.6, 0.3, 0.1];
+
+        assert_eq!(blend(&row, &centroids, 1), [0.0, 0.0, 0.0]);
+        let mixed = blend(&row, &centroids, 2);
+        assert!((mixed[0] - 100.0 / 3.0).abs() < 1e-4);
+        assert_eq!(mixed[2], 0.0);
+    }
+}
@@ -0,0 +1,327 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::initializer::Initializer;
+use crate::kmeans::types::{Assignments, Centroids};
+use crate::kmeans::utils::{find_closest_centroid, has_converged};
+use crate::types::VectorExt;
+use itertools::izip;
+
+// Added to the diagonal of every covariance, so that clusters of a single color
+// still have an invertible covariance
+const REGULARIZATION: f64 = 1e-3;
+const LOG_2PI: f64 = 1.8378770664093453;
+
+pub type Covariance = [[f32; 3]; 3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CovarianceType {
+    // Independent variance per channel, for axis-aligned ellipsoids
+    Diagonal,
+    // Full 3x3 covariance, for ellipsoids in any orientation
+    Full,
+}
+
+#[derive(Debug, Clone)]
+pub struct GaussianMixture<T> {
+    // Mixing weights, summing to 1
+    pub weights: Vec<f32>,
+    pub means: Centroids<T>,
+    // Covariances of the color channels
+    pub covariances: Vec<Covariance>,
+    // Responsibility-weighted log-likelihood of the data under each component
+    pub log_likelihoods: Vec<f32>,
+    // Log-likelihood of the data under the whole mixture
+    pub log_likelihood: f32,
+}
+
+// A component, prepared for evaluating log densities
+struct Component {
+    log_weight: f64,
+    mean: [f64; 3],
+    // Lower triangular Cholesky factor of the covariance
+    cholesky: [[f64; 3]; 3],
+    log_determinant: f64,
+}
+
+impl Component {
+    fn new(weight: f64, mean: [f64; 3], covariance: &[[f64; 3]; 3]) -> Self {
+        let cholesky = cholesky(covariance);
+        let log_determinant = 2.0 * (0..3).map(|i| cholesky[i][i].ln()).sum::<f64>();
+        Component {
+            log_weight: weight.ln(),
+            mean,
+            cholesky,
+            log_determinant,
+        }
+    }
+
+    // log N(x | mean, covariance), without the mixing weight
+    fn log_density(&self, x: &[f64; 3]) -> f64 {
+        // Solve L y = x - mean by forward substitution, so |y|² is the Mahalanobis distance
+        let l = &self.cholesky;
+        let mut y = [0.0; 3];
+        for i in 0..3 {
+            let dot: f64 = (0..i).map(|j| l[i][j] * y[j]).sum();
+            y[i] = (x[i] - self.mean[i] - dot) / l[i][i];
+        }
+        let mahalanobis: f64 = y.iter().map(|v| v * v).sum();
+        -0.5 * (3.0 * LOG_2PI + self.log_determinant + mahalanobis)
+    }
+}
+
+fn cholesky(a: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut l = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..=i {
+            let dot: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            if i == j {
+                l[i][i] = (a[i][i] - dot).max(REGULARIZATION).sqrt();
+            } else {
+                l[i][j] = (a[i][j] - dot) / l[j][j];
+            }
+        }
+    }
+    l
+}
+
+fn color<T: VectorExt>(pixel: &T) -> [f64; 3] {
+    [pixel[0] as f64, pixel[1] as f64, pixel[2] as f64]
+}
+
+// Running sums for the M step
+struct Statistics<T> {
+    responsibilities: Vec<f64>,
+    sums: Vec<T>,
+    color_sums: Vec<[f64; 3]>,
+    color_squares: Vec<[[f64; 3]; 3]>,
+}
+
+impl<T: VectorExt> Statistics<T> {
+    fn new(k: usize) -> Self {
+        Statistics {
+            responsibilities: vec![0.0; k],
+            sums: vec![T::zero(); k],
+            color_sums: vec![[0.0; 3]; k],
+            color_squares: vec![[[0.0; 3]; 3]; k],
+        }
+    }
+
+    fn add(&mut self, cluster: usize, pixel: &T, responsibility: f64) {
+        let x = color(pixel);
+        self.responsibilities[cluster] += responsibility;
+        self.sums[cluster] = self.sums[cluster].add(&pixel.mul_scalar(responsibility as f32));
+        for i in 0..3 {
+            self.color_sums[cluster][i] += responsibility * x[i];
+            for j in 0..3 {
+                self.color_squares[cluster][i][j] += responsibility * x[i] * x[j];
+            }
+        }
+    }
+
+    // New weights, means and covariances. Components that no pixel is responsible
+    // for keep their previous parameters.
+    fn update(
+        &self,
+        n: f64,
+        covariance_type: CovarianceType,
+        weights: &mut [f64],
+        means: &mut [T],
+        covariances: &mut [[[f64; 3]; 3]],
+    ) {
+        for cluster in 0..weights.len() {
+            let total = self.responsibilities[cluster];
+            if total <= 0.0 {
+                continue;
+            }
+            weights[cluster] = total / n;
+
+            let mean = self.color_sums[cluster].map(|sum| sum / total);
+            means[cluster] = self.sums[cluster].div_scalar(total as f32);
+            for (i, channel) in mean.iter().enumerate() {
+                means[cluster][i] = *channel as f32;
+            }
+
+            let covariance = &mut covariances[cluster];
+            for i in 0..3 {
+                for j in 0..3 {
+                    covariance[i][j] = if i == j || covariance_type == CovarianceType::Full {
+                        self.color_squares[cluster][i][j] / total - mean[i] * mean[j]
+                    } else {
+                        0.0
+                    };
+                }
+                covariance[i][i] = covariance[i][i].max(0.0) + REGULARIZATION;
+            }
+        }
+    }
+}
+
+fn components<T: VectorExt>(
+    weights: &[f64],
+    means: &[T],
+    covariances: &[[[f64; 3]; 3]],
+) -> Vec<Component> {
+    izip!(weights, means, covariances)
+        .map(|(&weight, mean, covariance)| Component::new(weight, color(mean), covariance))
+        .collect()
+}
+
+// log(sum(exp(values))), computed without overflow
+fn log_sum_exp(values: &[f64]) -> f64 {
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    max + values.iter().map(|v| (v - max).exp()).sum::<f64>().ln()
+}
+
+// Fits a mixture of k gaussians with expectation maximization (Dempster et al., 1977).
+// The means start from k-means++ and the covariances from the hard clusters around them.
+// Stops when no mean moves more than the tolerance.
+pub fn gaussian_mixture<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+    covariance_type: CovarianceType,
+) -> (Assignments, GaussianMixture<T>) {
+    let k = config.k;
+    let n = data.len() as f64;
+    let mut means = Initializer::KMeansPlusPlus.initialize_centroids(data, k, config.seed);
+    let k = means.len();
+    let mut weights = vec![1.0 / k as f64; k];
+    let mut covariances = vec![[[0.0; 3]; 3]; k];
+
+    let mut statistics = Statistics::new(k);
+    for pixel in data {
+        statistics.add(find_closest_centroid(pixel, &means), pixel, 1.0);
+    }
+    statistics.update(
+        n,
+        covariance_type,
+        &mut weights,
+        &mut means,
+        &mut covariances,
+    );
+
+    let mut log_densities = vec![0.0; k];
+    for _ in 0..config.max_iterations {
+        let components = components(&weights, &means, &covariances);
+
+        let mut statistics = Statistics::new(k);
+        for pixel in data {
+            let x = color(pixel);
+            for (log_density, component) in log_densities.iter_mut().zip(components.iter()) {
+                *log_density = component.log_weight + component.log_density(&x);
+            }
+            let total = log_sum_exp(&log_densities);
+            for (cluster, log_density) in log_densities.iter().enumerate() {
+                statistics.add(cluster, pixel, (log_density - total).exp());
+            }
+        }
+
+        let previous_means = means.clone();
+        statistics.update(
+            n,
+            covariance_type,
+            &mut weights,
+            &mut means,
+            &mut covariances,
+        );
+        if has_converged(&previous_means, &means, config.tolerance) {
+            break;
+        }
+    }
+
+    // Final E step, for the assignments and likelihoods of the returned parameters
+    let components = components(&weights, &means, &covariances);
+    let mut assignments = Vec::with_capacity(data.len());
+    let mut log_likelihoods = vec![0.0; k];
+    let mut log_likelihood = 0.0;
+    for pixel in data {
+        let x = color(pixel);
+        for (log_density, component) in log_densities.iter_mut().zip(components.iter()) {
+            *log_density = component.log_weight + component.log_density(&x);
+        }
+        let total = log_sum_exp(&log_densities);
+        log_likelihood += total;
+
+        let mut best = 0;
+        for (cluster, (log_density, component)) in
+            log_densities.iter().zip(components.iter()).enumerate()
+        {
+            let responsibility = (log_density - total).exp();
+            log_likelihoods[cluster] += responsibility * (log_density - component.log_weight);
+            if *log_density > log_densities[best] {
+                best = cluster;
+            }
+        }
+        assignments.push(best);
+    }
+
+    let mixture = GaussianMixture {
+        weights: weights.iter().map(|&weight| weight as f32).collect(),
+        means,
+        covariances: covariances
+            .iter()
+            .map(|covariance| covariance.map(|row| row.map(|value| value as f32)))
+            .collect(),
+        log_likelihoods: log_likelihoods.iter().map(|&value| value as f32).collect(),
+        log_likelihood: log_likelihood as f32,
+    };
+    (assignments, mixture)
+}
+
+pub fn kmeans_gmm<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+    covariance_type: CovarianceType,
+) -> (Assignments, Centroids<T>) {
+    let (assignments, mixture) = gaussian_mixture(data, config, covariance_type);
+    (assignments, mixture.means)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec3;
+
+    #[test]
+    fn test_full_covariance_captures_elongated_cluster() {
+        // A diagonal streak of colors and a compact blob
+        let mut data: Vec<Vec3> = (0..300)
+            .map(|i| {
+                let t = (i % 100) as f32;
+                let wobble = (i % 3) as f32;
+                [50.0 + t, 50.0 + t + wobble, 60.0 + wobble]
+            })
+            .collect();
+        data.extend((0..300).map(|i| {
+            let wobble = (i % 5) as f32;
+            [220.0 + wobble, 30.0, 200.0 - wobble]
+        }));
+        let config = KMeansConfig {
+            k: 2,
+            seed: Some(4),
+            ..Default::default()
+        };
+
+        let (full_assignments, full) = gaussian_mixture(&data, &config, CovarianceType::Full);
+        let (diagonal_assignments, diagonal) =
+            gaussian_mixture(&data, &config, CovarianceType::Diagonal);
+
+        assert!((full.weights.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+        assert!(full_assignments[..300]
+            .iter()
+            .all(|&c| c == full_assignments[0]));
+        assert!(full_assignments[300..]
+            .iter()
+            .all(|&c| c != full_assignments[0]));
+
+        // The streak's red and green channels move together
+        let streak = full_assignments[0];
+        let covariance = full.covariances[streak];
+        let correlation = covariance[0][1] / (covariance[0][0].sqrt() * covariance[1][1].sqrt());
+        assert!(correlation > 0.99, "correlation {}", correlation);
+        let diagonal_streak = diagonal_assignments[0];
+        assert_eq!(diagonal.covariances[diagonal_streak][0][1], 0.0);
+
+        // A full covariance models the streak far better than a diagonal one
+        assert!(full.log_likelihood > diagonal.log_likelihood);
+        assert!(full.log_likelihoods[streak] > diagonal.log_likelihoods[diagonal_streak]);
+    }
+}
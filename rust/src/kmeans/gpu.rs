@@ -13,7 +13,9 @@ use crate::types::{Vec4, Vec4u};
 use self::lloyd_gpu1::LloydAssignmentsOnly;
 use self::lloyd_gpu2::LloydAssignmentsAndCentroids;
 
+use super::distance::SquaredEuclideanDistance;
 use super::types::KMeansError;
+use super::utils::{inertia, restart_seed};
 use super::KMeansAlgorithm;
 
 #[derive(Debug, Clone, Copy)]
@@ -74,14 +76,53 @@ impl KMeansGpu {
         }
     }
 
+    pub fn config(&self) -> &KMeansConfig {
+        &self.config
+    }
+
     pub fn run(&self, data: &[Vec4u]) -> Result<(Vec<usize>, Vec<Vec4>), KMeansError> {
         block_on(self.run_async(data))
     }
 
     pub async fn run_async(&self, data: &[Vec4u]) -> Result<(Vec<usize>, Vec<Vec4>), KMeansError> {
+        let restarts = self.config.restarts();
+        if restarts == 1 {
+            return self.run_once(data, self.config.seed).await;
+        }
+
+        let pixels: Vec<Vec4> = data
+            .iter()
+            .map(|v| [v[0] as f32, v[1] as f32, v[2] as f32, v[3] as f32])
+            .collect();
+        let mut best: Option<(SquaredEuclideanDistance, Vec<usize>, Vec<Vec4>)> = None;
+        for restart in 0..restarts {
+            let (assignments, centroids) = self
+                .run_once(data, restart_seed(self.config.seed, restart))
+                .await?;
+            let inertia = inertia(&pixels, &assignments, &centroids);
+            if best
+                .as_ref()
+                .is_none_or(|(best_inertia, _, _)| inertia < *best_inertia)
+            {
+                best = Some((inertia, assignments, centroids));
+            }
+        }
+        let (_, assignments, centroids) = best.unwrap();
+        Ok((assignments, centroids))
+    }
+
+    async fn run_once(
+        &self,
+        data: &[Vec4u],
+        seed: Option<u64>,
+    ) -> Result<(Vec<usize>, Vec<Vec4>), KMeansError> {
         match &self.algorithm {
-            AlgorithmImpl::LloydAssignmentsOnly(lloyd) => lloyd.run_async(data).await,
-            AlgorithmImpl::LloydAssignmentsAndCentroids(lloyd) => lloyd.run_async(data).await,
+            AlgorithmImpl::LloydAssignmentsOnly(lloyd) => {
+                lloyd.run_async_with_seed(data, seed).await
+            }
+            AlgorithmImpl::LloydAssignmentsAndCentroids(lloyd) => {
+                lloyd.run_async_with_seed(data, seed).await
+            }
         }
     }
 }
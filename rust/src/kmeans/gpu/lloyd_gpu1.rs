@@ -1,11 +1,11 @@
 use super::buffers::MappableBuffer;
 use super::common::common_wgpu_setup;
 use crate::kmeans::types::KMeansResult;
-use crate::kmeans::utils::has_converged;
+use crate::kmeans::utils::{
+    cluster_means, fill_empty_clusters, finish_empty_clusters, has_converged,
+};
 use crate::kmeans::KMeansConfig;
-use crate::types::VectorExt;
 use crate::types::{Vec4, Vec4u};
-use futures::executor::block_on;
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages,
@@ -36,13 +36,6 @@ pub struct LloydAssignmentsOnly {
 }
 
 impl LloydAssignmentsOnly {
-    // useful because the initialization function is big
-    // and we don't want to recompile the shader
-    // every time we change the number of clusters
-    pub fn set_k(&mut self, k: usize) {
-        self.config.k = k;
-    }
-
     fn make_bind_group_layout(device: &Device) -> BindGroupLayout {
         let entries = [
             // Pixel Group
@@ -206,35 +199,37 @@ impl LloydAssignmentsOnly {
         })
     }
 
-    pub fn run(&self, pixels: &[Vec4u]) -> KMeansResult<Vec4> {
-        block_on(self.run_async(pixels))
-    }
-
-    pub async fn run_async(&self, pixels: &[Vec4u]) -> KMeansResult<Vec4> {
+    // Runs with a different seed than the configured one, for restarts
+    pub async fn run_async_with_seed(
+        &self,
+        pixels: &[Vec4u],
+        seed: Option<u64>,
+    ) -> KMeansResult<Vec4> {
         let vec4_pixels: Vec<Vec4> = pixels
             .iter()
             .map(|v| [v[0] as f32, v[1] as f32, v[2] as f32, v[3] as f32])
             .collect();
-        let mut centroids: Vec<Vec4> = self.config.initializer.initialize_centroids(
-            &vec4_pixels,
-            self.config.k,
-            self.config.seed,
-        );
+        let mut centroids: Vec<Vec4> =
+            self.config
+                .initializer
+                .initialize_centroids(&vec4_pixels, self.config.k, seed);
 
-        let mut assignments: Vec<u32> = vec![0; pixels.len()];
+        let mut assignments: Vec<usize> = vec![0; pixels.len()];
 
         let process_buffers = self
-            .prepare_buffers(pixels, &centroids, &assignments)
+            .prepare_buffers(pixels, &centroids, &vec![0u32; pixels.len()])
             .unwrap();
 
         let mut iterations = 0;
 
         while iterations < self.config.max_iterations {
-            let (new_assignments, new_centroids) =
-                self.run_iteration(&pixels, &process_buffers).await?;
+            let (new_assignments, new_centroids) = self
+                .run_iteration(&pixels, &vec4_pixels, &centroids, &process_buffers)
+                .await?;
 
             if has_converged(&centroids, &new_centroids, self.config.tolerance) {
                 centroids = new_centroids;
+                assignments = new_assignments;
                 break;
             }
 
@@ -249,17 +244,20 @@ impl LloydAssignmentsOnly {
             iterations += 1;
         }
 
-        Ok((
-            assignments.into_iter().map(|a| a as usize).collect(),
+        Ok(finish_empty_clusters(
+            assignments,
             centroids,
+            self.config.empty_cluster_policy,
         ))
     }
 
     async fn run_iteration(
         &self,
         pixels: &[Vec4u],
+        vec4_pixels: &[Vec4],
+        centroids: &[Vec4],
         process_buffers: &ProcessBuffers,
-    ) -> Result<(Vec<u32>, Vec<Vec4>), &'static str> {
+    ) -> Result<(Vec<usize>, Vec<Vec4>), &'static str> {
         let mut encoder = self
             .device
             .create_command_encoder(&CommandEncoderDescriptor { label: None });
@@ -289,30 +287,17 @@ impl LloydAssignmentsOnly {
             .assignment_buffer
             .read_back(&self.device)
             .await?;
-        let new_centroids = self.get_new_centroids(pixels, &assignments);
+        let mut assignments: Vec<usize> = assignments.into_iter().map(|a| a as usize).collect();
+        fill_empty_clusters(
+            vec4_pixels,
+            &mut assignments,
+            centroids,
+            self.config.empty_cluster_policy,
+        );
+        let new_centroids = cluster_means(vec4_pixels, &assignments, centroids);
 
         Ok((assignments, new_centroids))
     }
-
-    fn get_new_centroids(&self, pixels: &[Vec4u], assignments: &[u32]) -> Vec<Vec4> {
-        let mut new_centroids: Vec<Vec4> = vec![];
-
-        let mut centroid_sums: Vec<Vec4> = vec![[0.0; 4]; self.config.k];
-        let mut centroid_counts: Vec<u32> = vec![0; self.config.k];
-        for (pixel, assignment) in pixels.iter().zip(assignments.iter()) {
-            centroid_sums[*assignment as usize] = centroid_sums[*assignment as usize].add(&[
-                pixel[0] as f32,
-                pixel[1] as f32,
-                pixel[2] as f32,
-                pixel[3] as f32,
-            ]);
-            centroid_counts[*assignment as usize] += 1;
-        }
-        for (centroid_sum, centroid_count) in centroid_sums.iter().zip(centroid_counts.iter()) {
-            new_centroids.push(centroid_sum.div_scalar(*centroid_count as f32));
-        }
-        new_centroids
-    }
 }
 
 #[cfg(test)]
@@ -332,6 +317,8 @@ mod tests {
             algorithm: GpuAlgorithm::LloydAssignmentsOnly.into(),
             initializer: Initializer::Random,
             seed: Some(42),
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
         }
     }
 
@@ -351,7 +338,8 @@ mod tests {
         ];
 
         let kmeans = block_on(LloydAssignmentsOnly::from_config(config));
-        let (assignments, centroids) = block_on(kmeans.run_async(&pixels)).unwrap();
+        let (assignments, centroids) =
+            block_on(kmeans.run_async_with_seed(&pixels, kmeans.config.seed)).unwrap();
 
         assert_eq!(assignments.len(), pixels.len());
 
@@ -378,6 +366,8 @@ mod tests {
             algorithm: GpuAlgorithm::LloydAssignmentsOnly.into(),
             initializer: Initializer::Random,
             seed: Some(42),
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
         };
 
         let pixels: Vec<Vec4u> = vec![
@@ -390,7 +380,8 @@ mod tests {
         ];
 
         let kmeans = block_on(LloydAssignmentsOnly::from_config(config));
-        let (assignments, centroids) = block_on(kmeans.run_async(&pixels)).unwrap();
+        let (assignments, centroids) =
+            block_on(kmeans.run_async_with_seed(&pixels, kmeans.config.seed)).unwrap();
 
         assert_eq!(assignments.len(), pixels.len());
 
@@ -408,7 +399,8 @@ mod tests {
         let pixels: Vec<Vec4u> = vec![];
 
         let kmeans = block_on(LloydAssignmentsOnly::from_config(config));
-        let (assignments, centroids) = block_on(kmeans.run_async(&pixels)).unwrap();
+        let (assignments, centroids) =
+            block_on(kmeans.run_async_with_seed(&pixels, kmeans.config.seed)).unwrap();
 
         assert_eq!(assignments.len(), 0);
         assert_eq!(centroids.len(), 0);
@@ -434,7 +426,8 @@ mod tests {
             })
             .collect();
 
-        let (assignments, centroids) = block_on(kmeans.run_async(&pixels)).unwrap();
+        let (assignments, centroids) =
+            block_on(kmeans.run_async_with_seed(&pixels, kmeans.config.seed)).unwrap();
 
         assert_eq!(assignments.len(), pixels.len());
         assert_eq!(centroids.len(), config.k);
@@ -1,10 +1,11 @@
 use super::buffers::MappableBuffer;
 use super::common::common_wgpu_setup;
 use crate::kmeans::types::KMeansResult;
-use crate::kmeans::utils::has_converged;
+use crate::kmeans::utils::{
+    cluster_means, fill_empty_clusters, finish_empty_clusters, has_converged,
+};
 use crate::kmeans::KMeansConfig;
 use crate::types::{Vec4, Vec4u};
-use futures::executor::block_on;
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages,
@@ -37,10 +38,6 @@ pub struct LloydAssignmentsAndCentroids {
 }
 
 impl LloydAssignmentsAndCentroids {
-    pub fn set_k(&mut self, k: usize) {
-        self.config.k = k;
-    }
-
     fn make_bind_group_layout(device: &Device) -> BindGroupLayout {
         device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("kmeans_bind_group_layout"),
@@ -235,31 +232,44 @@ impl LloydAssignmentsAndCentroids {
         })
     }
 
-    pub fn run(&self, pixels: &[Vec4u]) -> KMeansResult<Vec4> {
-        block_on(self.run_async(pixels))
-    }
-
-    pub async fn run_async(&self, pixels: &[Vec4u]) -> KMeansResult<Vec4> {
+    // Runs with a different seed than the configured one, for restarts
+    pub async fn run_async_with_seed(
+        &self,
+        pixels: &[Vec4u],
+        seed: Option<u64>,
+    ) -> KMeansResult<Vec4> {
         let vec4_pixels: Vec<Vec4> = pixels
             .iter()
             .map(|v| [v[0] as f32, v[1] as f32, v[2] as f32, v[3] as f32])
             .collect();
-        let mut centroids: Vec<Vec4> = self.config.initializer.initialize_centroids(
-            &vec4_pixels,
-            self.config.k,
-            self.config.seed,
-        );
+        let mut centroids: Vec<Vec4> =
+            self.config
+                .initializer
+                .initialize_centroids(&vec4_pixels, self.config.k, seed);
 
         let process_buffers = self.prepare_buffers(pixels, &centroids).unwrap();
 
         let mut iterations = 0;
 
         while iterations < self.config.max_iterations {
-            let new_centroids = self.run_iteration(&process_buffers, pixels.len()).await?;
+            let mut new_centroids = self.run_iteration(&process_buffers, pixels.len()).await?;
 
             if has_converged(&centroids, &new_centroids, self.config.tolerance) {
-                centroids = new_centroids;
-                break;
+                // The shader leaves empty clusters where they are. Reading the assignments
+                // back every iteration would defeat the point of this variant, so they're
+                // only checked once the centroids settle.
+                let mut assignments = self.read_assignments(&process_buffers).await?;
+                let moved = fill_empty_clusters(
+                    &vec4_pixels,
+                    &mut assignments,
+                    &centroids,
+                    self.config.empty_cluster_policy,
+                );
+                if moved.is_empty() {
+                    centroids = new_centroids;
+                    break;
+                }
+                new_centroids = cluster_means(&vec4_pixels, &assignments, &new_centroids);
             }
 
             self.queue.write_buffer(
@@ -274,7 +284,11 @@ impl LloydAssignmentsAndCentroids {
 
         let assignments = self.read_assignments(&process_buffers).await?;
 
-        Ok((assignments, centroids))
+        Ok(finish_empty_clusters(
+            assignments,
+            centroids,
+            self.config.empty_cluster_policy,
+        ))
     }
 
     async fn run_iteration(
@@ -348,6 +362,8 @@ mod tests {
             algorithm: GpuAlgorithm::LloydAssignmentsAndCentroids.into(),
             initializer: Initializer::Random,
             seed: Some(42),
+            n_init: 1,
+            empty_cluster_policy: Default::default(),
         }
     }
 
@@ -367,7 +383,8 @@ mod tests {
         ];
 
         let kmeans = block_on(LloydAssignmentsAndCentroids::from_config(config));
-        let (assignments, centroids) = block_on(kmeans.run_async(&pixels)).unwrap();
+        let (assignments, centroids) =
+            block_on(kmeans.run_async_with_seed(&pixels, kmeans.config.seed)).unwrap();
 
         assert_eq!(assignments.len(), pixels.len());
 
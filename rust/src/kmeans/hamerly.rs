@@ -2,8 +2,11 @@ use crate::kmeans::config::KMeansConfig;
 use crate::kmeans::distance::{
     euclidean_distance_squared, EuclideanDistance, SquaredEuclideanDistance,
 };
-use crate::kmeans::types::{Assignments, CentroidCounts, CentroidSums, Centroids};
-use crate::kmeans::utils::has_converged;
+use crate::kmeans::types::{Assignments, CentroidCounts, CentroidSums, CentroidWeights, Centroids};
+use crate::kmeans::utils::{
+    fill_empty_clusters, finish_empty_clusters, has_converged, move_centroids, reset_moved_bounds,
+    weighted_cluster_sums,
+};
 use crate::types::VectorExt;
 use itertools::izip;
 
@@ -14,21 +17,58 @@ pub fn kmeans_hamerly<T: VectorExt>(
     data: &[T],
     config: &KMeansConfig,
 ) -> (Assignments, Centroids<T>) {
+    let centroids = config
+        .initializer
+        .initialize_centroids(data, config.k, config.seed);
+    kmeans_hamerly_from_centroids(data, config, centroids)
+}
+
+// Runs Hamerly's algorithm starting from the given centroids instead of the
+// configured initializer. k is taken from the number of centroids.
+pub fn kmeans_hamerly_from_centroids<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+    centroids: Centroids<T>,
+) -> (Assignments, Centroids<T>) {
+    hamerly(data, None, config, centroids)
+}
+
+// Hamerly's algorithm on points that each count as `weight` copies of themselves
+pub fn kmeans_hamerly_weighted<T: VectorExt>(
+    data: &[T],
+    weights: &[f32],
+    config: &KMeansConfig,
+) -> (Assignments, Centroids<T>) {
+    let centroids =
+        config
+            .initializer
+            .initialize_weighted_centroids(data, weights, config.k, config.seed);
+    hamerly(data, Some(weights), config, centroids)
+}
+
+// Without weights, every point counts once
+fn hamerly<T: VectorExt>(
+    data: &[T],
+    weights: Option<&[f32]>,
+    config: &KMeansConfig,
+    centroids: Centroids<T>,
+) -> (Assignments, Centroids<T>) {
+    let k = centroids.len();
     let (
         mut centroids,
         mut centroid_sums,
+        mut centroid_weights,
         mut centroid_counts,
         mut upper_bounds,
         mut lower_bounds,
         mut clusters,
-    ) = initialize_hamerly(data, config);
+    ) = initialize_hamerly(data, weights, centroids);
 
-    let mut centroid_move_distances = vec![EuclideanDistance(0.0); config.k];
-    let mut centroid_neighbor_distances = vec![EuclideanDistance(f32::MAX); config.k];
+    let mut centroid_move_distances = vec![EuclideanDistance(0.0); k];
+    let mut centroid_neighbor_distances = vec![EuclideanDistance(f32::MAX); k];
     let mut new_centroids = centroids.clone();
 
     let num_pixels = data.len();
-    let k = config.k;
 
     // I need to check this but I think it should ensure
     // we don't get any bounds checks?
@@ -38,8 +78,8 @@ pub fn kmeans_hamerly<T: VectorExt>(
     for _ in 0..config.max_iterations {
         compute_neighbor_distances(&centroids, &mut centroid_neighbor_distances);
 
-        for (pixel, assigned_cluster, upper_bound, lower_bound) in
-            izip!(data, &mut clusters, &mut upper_bounds, &mut lower_bounds)
+        for (i, (pixel, assigned_cluster, upper_bound, lower_bound)) in
+            izip!(data, &mut clusters, &mut upper_bounds, &mut lower_bounds).enumerate()
         {
             let m = lower_bound.max(centroid_neighbor_distances[*assigned_cluster] / (2.).into());
             if *upper_bound <= m {
@@ -57,21 +97,36 @@ pub fn kmeans_hamerly<T: VectorExt>(
             *upper_bound = best_distance;
             *lower_bound = second_best_distance;
             if best_index != *assigned_cluster {
-                centroid_sums[*assigned_cluster] = centroid_sums[*assigned_cluster].sub(pixel);
+                let weight = weights.map_or(1.0, |weights| weights[i]);
+                let weighted_pixel = pixel.mul_scalar(weight);
+                centroid_sums[*assigned_cluster] =
+                    centroid_sums[*assigned_cluster].sub(&weighted_pixel);
+                centroid_weights[*assigned_cluster] -= weight;
                 centroid_counts[*assigned_cluster] -= 1;
-                centroid_sums[best_index] = centroid_sums[best_index].add(pixel);
+                centroid_sums[best_index] = centroid_sums[best_index].add(&weighted_pixel);
+                centroid_weights[best_index] += weight;
                 centroid_counts[best_index] += 1;
 
                 *assigned_cluster = best_index;
             }
         }
 
+        if centroid_counts.contains(&0) {
+            let moved =
+                fill_empty_clusters(data, &mut clusters, &centroids, config.empty_cluster_policy);
+            if !moved.is_empty() {
+                (centroid_sums, centroid_weights, centroid_counts) =
+                    weighted_cluster_sums(data, weights, &clusters, k);
+                reset_moved_bounds(&moved, &mut upper_bounds, &mut lower_bounds);
+            }
+        }
+
         // Move centroids into new_centroids (we swap later)
         move_centroids(
-            &mut centroids,
+            &centroids,
             &mut new_centroids,
-            &mut centroid_sums,
-            &mut centroid_counts,
+            &centroid_sums,
+            |j| centroid_weights[j],
             &mut centroid_move_distances,
         );
 
@@ -90,49 +145,51 @@ pub fn kmeans_hamerly<T: VectorExt>(
             &clusters,
         )
     }
-    (clusters.to_vec(), centroids.to_vec())
+    finish_empty_clusters(clusters, centroids, config.empty_cluster_policy)
 }
 
 fn initialize_hamerly<T: VectorExt>(
     data: &[T],
-    config: &KMeansConfig,
+    weights: Option<&[f32]>,
+    centroids: Centroids<T>,
 ) -> (
     Centroids<T>,
     CentroidSums<T>,
+    CentroidWeights,
     CentroidCounts,
     UpperBounds,
     LowerBounds,
     Assignments,
 ) {
-    // indicex of the cluster each pixel belongs to
-    let centroids = config
-        .initializer
-        .initialize_centroids(data, config.k, config.seed);
-
+    let k = centroids.len();
     let num_pixels = data.len();
     let mut clusters = vec![0; num_pixels];
     let mut upper_bounds = vec![EuclideanDistance(0.0); num_pixels];
     let mut lower_bounds = vec![EuclideanDistance(0.0); num_pixels];
 
-    let mut centroid_sums = vec![T::zero(); config.k];
-    let mut centroid_counts = vec![0; config.k];
+    let mut centroid_sums = vec![T::zero(); k];
+    let mut centroid_weights = vec![0.0; k];
+    let mut centroid_counts = vec![0; k];
 
-    assert!(data.len() >= config.k);
-    assert!(centroid_sums.len() == config.k);
+    assert!(data.len() >= k);
+    assert!(centroid_sums.len() == k);
 
     for i in 0..num_pixels {
+        let weight = weights.map_or(1.0, |weights| weights[i]);
         let (best_distance, second_best_distance, best_index) =
             find_best_and_second_best(&centroids, &data[i]);
 
         upper_bounds[i] = best_distance;
         lower_bounds[i] = second_best_distance;
         clusters[i] = best_index;
-        centroid_sums[best_index] = centroid_sums[best_index].add(&data[i]);
+        centroid_sums[best_index] = centroid_sums[best_index].add(&data[i].mul_scalar(weight));
+        centroid_weights[best_index] += weight;
         centroid_counts[best_index] += 1;
     }
     (
         centroids,
         centroid_sums,
+        centroid_weights,
         centroid_counts,
         upper_bounds,
         lower_bounds,
@@ -219,20 +276,3 @@ fn compute_neighbor_distances<T: VectorExt>(centroids: &[T], distances: &mut [Eu
         }
     }
 }
-
-fn move_centroids<T: VectorExt>(
-    centroids: &mut [T],
-    new_centroids: &mut [T],
-    centroid_sums: &mut [T],
-    centroid_counts: &mut [usize],
-    centroid_move_distances: &mut [EuclideanDistance],
-) {
-    for (j, (current_centroid, new_centroid)) in
-        centroids.iter().zip(new_centroids.iter_mut()).enumerate()
-    {
-        *new_centroid = centroid_sums[j].div_scalar(centroid_counts[j] as f32);
-        // We need to square root here because the bounds check assumes true distances.
-        centroid_move_distances[j] =
-            euclidean_distance_squared(current_centroid, new_centroid).sqrt();
-    }
-}
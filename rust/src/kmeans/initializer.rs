@@ -1,16 +1,61 @@
 use crate::kmeans::distance::euclidean_distance_squared;
 use crate::kmeans::distance::SquaredEuclideanDistance;
-use crate::types::VectorExt;
+use crate::kmeans::median_cut::{median_cut, weighted_median_cut};
+use crate::kmeans::partition::{
+    pca_partition, variance_partition, weighted_pca_partition, weighted_variance_partition,
+};
+use crate::kmeans::utils::find_closest_centroid;
+use crate::kmeans::wu::{weighted_wu, wu};
+use crate::types::{Vec4, VectorExt};
+use rand::distributions::WeightedIndex;
 use rand::prelude::*;
+use rand::seq::index::sample_weighted;
 use rand::SeedableRng;
 
 #[derive(Debug, Clone)]
 pub enum Initializer {
     KMeansPlusPlus,
     Random,
+    MedianCut,
+    Wu,
+    KMeansParallel,
+    // k-means++ that samples several candidates per step and keeps the one that lowers
+    // the potential the most. 0 trials picks 2 + ln(k), as scikit-learn does.
+    GreedyKMeansPlusPlus { trials: usize },
+    // The initializers below don't use the seed, and always give the same centroids
+    // for the same data
+    Maximin,
+    VariancePartition,
+    PcaPartition,
+    // Start from the given centroids, e.g. an existing palette or the result of an
+    // earlier run. Extra centroids are dropped and missing ones are filled in
+    // farthest-first. Channels the data doesn't have are ignored.
+    Provided(Vec<Vec4>),
 }
 
 impl Initializer {
+    // The initializer for runs that split a single cluster, as in bisecting k-means.
+    // Provided centroids are for the whole image, so splits use k-means++ instead.
+    pub(crate) fn for_split(&self) -> Initializer {
+        match self {
+            Initializer::Provided(_) => Initializer::KMeansPlusPlus,
+            initializer => initializer.clone(),
+        }
+    }
+
+    // Whether the centroids only depend on the data, and not on the seed
+    pub fn is_seedless(&self) -> bool {
+        matches!(
+            self,
+            Initializer::MedianCut
+                | Initializer::Wu
+                | Initializer::Maximin
+                | Initializer::VariancePartition
+                | Initializer::PcaPartition
+                | Initializer::Provided(_)
+        )
+    }
+
     pub fn initialize_centroids<T: VectorExt>(
         &self,
         data: &[T],
@@ -20,11 +65,104 @@ impl Initializer {
         match self {
             Initializer::KMeansPlusPlus => kmeans_plus_plus(data, k, seed),
             Initializer::Random => initialize_random(data, k, seed),
+            Initializer::MedianCut => fill_farthest_first(data, median_cut(data, k), k),
+            Initializer::Wu => fill_farthest_first(data, wu(data, k), k),
+            Initializer::KMeansParallel => kmeans_parallel(data, None, k, seed),
+            Initializer::GreedyKMeansPlusPlus { trials } => fill_farthest_first(
+                data,
+                greedy_kmeans_plus_plus(data, None, k, *trials, seed),
+                k,
+            ),
+            Initializer::Maximin => maximin(data, k),
+            Initializer::VariancePartition => {
+                fill_farthest_first(data, variance_partition(data, k), k)
+            }
+            Initializer::PcaPartition => fill_farthest_first(data, pca_partition(data, k), k),
+            Initializer::Provided(centroids) => {
+                fill_farthest_first(data, provided(centroids, k), k)
+            }
+        }
+    }
+
+    // Centroids for points that each count as `weight` copies of themselves. Maximin and
+    // provided centroids don't depend on the weights. When the weighted points run out,
+    // the rest are filled in farthest-first from every point, so there are k centroids
+    // whenever there are k distinct points.
+    pub fn initialize_weighted_centroids<T: VectorExt>(
+        &self,
+        data: &[T],
+        weights: &[f32],
+        k: usize,
+        seed: Option<u64>,
+    ) -> Vec<T> {
+        match self {
+            Initializer::KMeansPlusPlus => fill_farthest_first(
+                data,
+                weighted_kmeans_plus_plus(data, weights, k, &mut get_seedable_rng(seed)),
+                k,
+            ),
+            // Without enough positive weights to sample from, sample uniformly
+            Initializer::Random => {
+                let mut rng = get_seedable_rng(seed);
+                match sample_weighted(&mut rng, data.len(), |i| weights[i], k.min(data.len())) {
+                    Ok(indices) if indices.len() == k.min(data.len()) => {
+                        indices.iter().map(|i| data[i]).collect()
+                    }
+                    _ => initialize_random(data, k, seed),
+                }
+            }
+            Initializer::MedianCut => {
+                fill_farthest_first(data, weighted_median_cut(data, weights, k), k)
+            }
+            Initializer::Wu => fill_farthest_first(data, weighted_wu(data, weights, k), k),
+            Initializer::KMeansParallel => {
+                fill_farthest_first(data, kmeans_parallel(data, Some(weights), k, seed), k)
+            }
+            Initializer::GreedyKMeansPlusPlus { trials } => fill_farthest_first(
+                data,
+                greedy_kmeans_plus_plus(data, Some(weights), k, *trials, seed),
+                k,
+            ),
+            Initializer::Maximin => maximin(data, k),
+            Initializer::VariancePartition => {
+                fill_farthest_first(data, weighted_variance_partition(data, weights, k), k)
+            }
+            Initializer::PcaPartition => {
+                fill_farthest_first(data, weighted_pca_partition(data, weights, k), k)
+            }
+            Initializer::Provided(centroids) => {
+                fill_farthest_first(data, provided(centroids, k), k)
+            }
         }
     }
 }
 
-fn get_seedable_rng(seed: Option<u64>) -> StdRng {
+// The first k provided centroids, with the channels the data has
+fn provided<T: VectorExt>(centroids: &[Vec4], k: usize) -> Vec<T> {
+    centroids
+        .iter()
+        .take(k)
+        .map(|provided| {
+            let mut centroid = T::zero();
+            for (channel, value) in provided.iter().enumerate().take(T::CHANNELS) {
+                centroid[channel] = *value;
+            }
+            centroid
+        })
+        .collect()
+}
+
+// Picks a first centroid, with probability proportional to weight if there are weights
+fn choose_first<'a, T>(data: &'a [T], weights: Option<&[f32]>, rng: &mut StdRng) -> Option<&'a T> {
+    match weights {
+        None => data.choose(rng),
+        Some(weights) => WeightedIndex::new(weights)
+            .ok()
+            .map(|index| &data[index.sample(rng)]),
+    }
+}
+
+pub(crate) fn get_seedable_rng(seed: Option<u64>) -> StdRng {
     if let Some(seed) = seed {
         rand::rngs::StdRng::seed_from_u64(seed)
     } else {
@@ -76,6 +214,278 @@ fn kmeans_plus_plus<T: VectorExt>(data: &[T], k: usize, seed: Option<u64>) -> Ve
     centroids
 }
 
+// Greedy k-means++ (Arthur & Vassilvitskii, 2007, section 6).
+// Keeping the best of several D² samples per step makes the result much less
+// sensitive to the seed than plain k-means++.
+fn greedy_kmeans_plus_plus<T: VectorExt>(
+    data: &[T],
+    weights: Option<&[f32]>,
+    k: usize,
+    trials: usize,
+    seed: Option<u64>,
+) -> Vec<T> {
+    let weight = |i: usize| weights.map_or(1.0, |weights| weights[i]);
+    let mut centroids = Vec::with_capacity(k);
+    let mut rng = get_seedable_rng(seed);
+    let Some(first_centroid) = choose_first(data, weights, &mut rng) else {
+        return centroids;
+    };
+    if k == 0 {
+        return centroids;
+    }
+    centroids.push(*first_centroid);
+
+    let trials = match trials {
+        0 => 2 + (k as f64).ln() as usize,
+        trials => trials,
+    };
+
+    let mut distances: Vec<f32> = data
+        .iter()
+        .map(|pixel| euclidean_distance_squared(pixel, first_centroid).0)
+        .collect();
+    let mut candidate_distances = vec![0.0; data.len()];
+    let mut best_distances = vec![0.0; data.len()];
+
+    while centroids.len() < k {
+        let potentials = distances
+            .iter()
+            .enumerate()
+            .map(|(i, distance)| distance * weight(i));
+        // Every point with weight coincides with a centroid
+        let Ok(sampler) = WeightedIndex::new(potentials) else {
+            break;
+        };
+
+        let mut best: Option<(f64, T)> = None;
+        for _ in 0..trials {
+            let candidate = data[sampler.sample(&mut rng)];
+            let mut potential = 0.0;
+            for (i, (pixel, (distance, candidate_distance))) in data
+                .iter()
+                .zip(distances.iter().zip(candidate_distances.iter_mut()))
+                .enumerate()
+            {
+                *candidate_distance = distance.min(euclidean_distance_squared(pixel, &candidate).0);
+                potential += (weight(i) * *candidate_distance) as f64;
+            }
+
+            if best.is_none_or(|(best_potential, _)| potential < best_potential) {
+                best = Some((potential, candidate));
+                std::mem::swap(&mut best_distances, &mut candidate_distances);
+            }
+        }
+
+        centroids.push(best.unwrap().1);
+        std::mem::swap(&mut distances, &mut best_distances);
+    }
+    centroids
+}
+
+// Number of oversampling rounds for k-means||. Bahmani et al. found 5 rounds enough
+// in practice, no matter the data size.
+const KMEANS_PARALLEL_ROUNDS: usize = 5;
+// Expected number of candidates sampled per round, as a multiple of k
+const KMEANS_PARALLEL_OVERSAMPLING: f64 = 2.0;
+// Lloyd iterations on the weighted candidates after seeding them with k-means++
+const RECLUSTER_ITERATIONS: usize = 10;
+
+// Scalable k-means++ (Bahmani et al., 2012).
+// Instead of one pass per centroid, every round samples about 2k candidates at once
+// with probability proportional to their squared distance, so it takes a handful of
+// passes over the data. The candidates are then weighted by how many points are
+// closest to them and clustered down to k.
+fn kmeans_parallel<T: VectorExt>(
+    data: &[T],
+    weights: Option<&[f32]>,
+    k: usize,
+    seed: Option<u64>,
+) -> Vec<T> {
+    let weight = |i: usize| weights.map_or(1.0, |weights| weights[i]);
+    let mut rng = get_seedable_rng(seed);
+    let Some(first_candidate) = choose_first(data, weights, &mut rng) else {
+        return Vec::new();
+    };
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let mut candidates = vec![*first_candidate];
+    let mut nearest = vec![0; data.len()];
+    let mut distances: Vec<SquaredEuclideanDistance> = data
+        .iter()
+        .map(|pixel| euclidean_distance_squared(pixel, first_candidate))
+        .collect();
+
+    let oversampling = KMEANS_PARALLEL_OVERSAMPLING * k as f64;
+    for _ in 0..KMEANS_PARALLEL_ROUNDS {
+        let cost: f64 = distances
+            .iter()
+            .enumerate()
+            .map(|(i, distance)| (weight(i) * distance.0) as f64)
+            .sum();
+        if cost == 0.0 {
+            break;
+        }
+
+        let first_new = candidates.len();
+        for (i, (pixel, distance)) in data.iter().zip(distances.iter()).enumerate() {
+            if rng.gen::<f64>() < oversampling * (weight(i) * distance.0) as f64 / cost {
+                candidates.push(*pixel);
+            }
+        }
+
+        // Only the new candidates can be closer than before
+        for (pixel, (nearest, distance)) in data
+            .iter()
+            .zip(nearest.iter_mut().zip(distances.iter_mut()))
+        {
+            for (i, candidate) in candidates.iter().enumerate().skip(first_new) {
+                let candidate_distance = euclidean_distance_squared(pixel, candidate);
+                if candidate_distance < *distance {
+                    *distance = candidate_distance;
+                    *nearest = i;
+                }
+            }
+        }
+    }
+
+    if candidates.len() <= k {
+        return fill_farthest_first(data, candidates, k);
+    }
+
+    let mut candidate_weights = vec![0.0; candidates.len()];
+    for (i, &candidate) in nearest.iter().enumerate() {
+        candidate_weights[candidate] += weight(i);
+    }
+
+    let mut centroids = weighted_kmeans_plus_plus(&candidates, &candidate_weights, k, &mut rng);
+    weighted_lloyd(
+        &candidates,
+        &candidate_weights,
+        &mut centroids,
+        RECLUSTER_ITERATIONS,
+    );
+    centroids
+}
+
+// k-means++ where each point counts as `weight` copies of itself
+fn weighted_kmeans_plus_plus<T: VectorExt>(
+    points: &[T],
+    weights: &[f32],
+    k: usize,
+    rng: &mut StdRng,
+) -> Vec<T> {
+    let mut centroids = Vec::with_capacity(k);
+    let Ok(first) = WeightedIndex::new(weights) else {
+        return centroids;
+    };
+    centroids.push(points[first.sample(rng)]);
+
+    let mut distances: Vec<f32> = points
+        .iter()
+        .map(|point| euclidean_distance_squared(point, &centroids[0]).0)
+        .collect();
+    while centroids.len() < k {
+        let potentials = distances
+            .iter()
+            .zip(weights.iter())
+            .map(|(distance, weight)| distance * weight);
+        // Every point with weight coincides with a centroid
+        let Ok(next) = WeightedIndex::new(potentials) else {
+            break;
+        };
+
+        let centroid = points[next.sample(rng)];
+        centroids.push(centroid);
+        for (point, distance) in points.iter().zip(distances.iter_mut()) {
+            *distance = distance.min(euclidean_distance_squared(point, &centroid).0);
+        }
+    }
+    centroids
+}
+
+fn weighted_lloyd<T: VectorExt>(
+    points: &[T],
+    weights: &[f32],
+    centroids: &mut [T],
+    iterations: usize,
+) {
+    let mut sums = vec![T::zero(); centroids.len()];
+    let mut totals = vec![0.0; centroids.len()];
+    for _ in 0..iterations {
+        sums.fill(T::zero());
+        totals.fill(0.0);
+        for (point, &weight) in points.iter().zip(weights.iter()) {
+            let cluster = find_closest_centroid(point, centroids);
+            sums[cluster] = sums[cluster].add(&point.mul_scalar(weight));
+            totals[cluster] += weight;
+        }
+        for (centroid, (sum, &total)) in centroids.iter_mut().zip(sums.iter().zip(totals.iter())) {
+            if total > 0.0 {
+                *centroid = sum.div_scalar(total);
+            }
+        }
+    }
+}
+
+// Maximin (Katsavounidis et al., 1994): start from the brightest pixel, then keep
+// adding the pixel farthest from every centroid so far
+fn maximin<T: VectorExt>(data: &[T], k: usize) -> Vec<T> {
+    let brightness = |pixel: &T| pixel[0] * pixel[0] + pixel[1] * pixel[1] + pixel[2] * pixel[2];
+    let Some(first) = data
+        .iter()
+        .max_by(|a, b| brightness(a).total_cmp(&brightness(b)))
+    else {
+        return Vec::new();
+    };
+    if k == 0 {
+        return Vec::new();
+    }
+    fill_farthest_first(data, vec![*first], k)
+}
+
+// Box-splitting initializers can return fewer than k centroids when colors share a box.
+// Top them up with the points farthest from the existing centroids, which keeps them
+// deterministic.
+pub(crate) fn fill_farthest_first<T: VectorExt>(
+    data: &[T],
+    mut centroids: Vec<T>,
+    k: usize,
+) -> Vec<T> {
+    if centroids.len() >= k || data.is_empty() {
+        return centroids;
+    }
+
+    let mut distances: Vec<SquaredEuclideanDistance> = data
+        .iter()
+        .map(|pixel| {
+            centroids
+                .iter()
+                .map(|centroid| euclidean_distance_squared(pixel, centroid))
+                .fold(SquaredEuclideanDistance(f32::MAX), |a, b| a.min(b))
+        })
+        .collect();
+
+    while centroids.len() < k {
+        let (farthest, &distance) = distances
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+            .unwrap();
+        if distance.0 == 0.0 {
+            break; // every point already coincides with a centroid
+        }
+
+        let centroid = data[farthest];
+        centroids.push(centroid);
+        for (pixel, distance) in data.iter().zip(distances.iter_mut()) {
+            *distance = distance.min(euclidean_distance_squared(pixel, &centroid));
+        }
+    }
+    centroids
+}
+
 pub fn initialize_random<T: Copy>(data: &[T], k: usize, seed: Option<u64>) -> Vec<T> {
     // Seed the RNG if provided, otherwise use the current time
     let mut rng = {
@@ -93,3 +503,171 @@ pub fn initialize_random<T: Copy>(data: &[T], k: usize, seed: Option<u64>) -> Ve
 
     centroids
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec3;
+
+    fn gradient(n: usize) -> Vec<Vec3> {
+        (0..n)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 7.0) % 256.0, (i * 13.0) % 256.0, (i * 29.0) % 256.0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_kmeans_parallel_is_reproducible() {
+        let data = gradient(5000);
+        let first = Initializer::KMeansParallel.initialize_centroids(&data, 64, Some(42));
+        let second = Initializer::KMeansParallel.initialize_centroids(&data, 64, Some(42));
+
+        assert_eq!(first.len(), 64);
+        assert_eq!(first, second);
+
+        let data: Vec<Vec4> = data.iter().map(|p| [p[0], p[1], p[2], 255.0]).collect();
+        let centroids = Initializer::KMeansParallel.initialize_centroids(&data, 64, Some(42));
+        assert_eq!(centroids.len(), 64);
+    }
+
+    #[test]
+    fn test_greedy_kmeans_plus_plus_lowers_potential() {
+        let data = gradient(2000);
+        let potential = |centroids: &[Vec3]| -> f32 {
+            data.iter()
+                .map(|pixel| {
+                    euclidean_distance_squared(
+                        pixel,
+                        &centroids[find_closest_centroid(pixel, centroids)],
+                    )
+                    .0
+                })
+                .sum()
+        };
+
+        let mut plain = 0.0;
+        let mut greedy = 0.0;
+        for seed in 0..10 {
+            plain +=
+                potential(&Initializer::KMeansPlusPlus.initialize_centroids(&data, 16, Some(seed)));
+            let centroids = Initializer::GreedyKMeansPlusPlus { trials: 0 }.initialize_centroids(
+                &data,
+                16,
+                Some(seed),
+            );
+            assert_eq!(centroids.len(), 16);
+            greedy += potential(&centroids);
+        }
+        assert!(greedy < plain, "greedy {} >= plain {}", greedy, plain);
+    }
+
+    #[test]
+    fn test_deterministic_initializers_ignore_seed() {
+        let data = gradient(3000);
+        for initializer in [
+            Initializer::Maximin,
+            Initializer::VariancePartition,
+            Initializer::PcaPartition,
+        ] {
+            let first = initializer.initialize_centroids(&data, 32, None);
+            let second = initializer.initialize_centroids(&data, 32, None);
+            assert_eq!(first.len(), 32, "{:?}", initializer);
+            assert_eq!(first, second, "{:?}", initializer);
+        }
+    }
+
+    #[test]
+    fn test_provided_centroids_are_kept_and_topped_up() {
+        let data = gradient(500);
+        let provided = vec![[10.0, 20.0, 30.0, 255.0], [200.0, 100.0, 50.0, 255.0]];
+
+        let centroids = Initializer::Provided(provided).initialize_centroids(&data, 4, None);
+
+        assert_eq!(centroids.len(), 4);
+        assert_eq!(centroids[0], [10.0, 20.0, 30.0]);
+        assert_eq!(centroids[1], [200.0, 100.0, 50.0]);
+    }
+
+    #[test]
+    fn test_kmeans_parallel_with_few_colors() {
+        let data: Vec<Vec3> = (0..100)
+            .map(|i| if i % 2 == 0 { [0.0; 3] } else { [255.0; 3] })
+            .collect();
+        let centroids = Initializer::KMeansParallel.initialize_centroids(&data, 2, Some(1));
+
+        assert_eq!(centroids.len(), 2);
+        assert_ne!(centroids[0], centroids[1]);
+    }
+
+    #[test]
+    fn test_weighted_box_initializers_match_repeated_points() {
+        let colors = gradient(300);
+        let weights: Vec<f32> = (0..colors.len()).map(|i| (1 + i % 3) as f32).collect();
+        let repeated: Vec<Vec3> = colors
+            .iter()
+            .zip(weights.iter())
+            .flat_map(|(color, &weight)| std::iter::repeat_n(*color, weight as usize))
+            .collect();
+
+        for initializer in [
+            Initializer::MedianCut,
+            Initializer::Wu,
+            Initializer::VariancePartition,
+            Initializer::PcaPartition,
+        ] {
+            let sorted = |mut centroids: Vec<Vec3>| {
+                centroids.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                centroids
+            };
+            let expected = sorted(initializer.initialize_centroids(&repeated, 12, None));
+            let weighted =
+                sorted(initializer.initialize_weighted_centroids(&colors, &weights, 12, None));
+
+            assert_eq!(weighted.len(), expected.len(), "{:?}", initializer);
+            for (a, b) in weighted.iter().zip(expected.iter()) {
+                assert!(
+                    (0..3).all(|channel| (a[channel] - b[channel]).abs() < 1e-2),
+                    "{:?}: {:?} != {:?}",
+                    initializer,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_weighted_initializers_fill_in_when_weights_run_out() {
+        let data = gradient(20);
+        let zero = vec![0.0; data.len()];
+        let mut single = zero.clone();
+        single[3] = 1.0;
+
+        for initializer in [
+            Initializer::KMeansPlusPlus,
+            Initializer::Random,
+            Initializer::KMeansParallel,
+            Initializer::GreedyKMeansPlusPlus { trials: 0 },
+        ] {
+            let centroids = initializer.initialize_weighted_centroids(&data, &zero, 5, Some(1));
+            assert_eq!(centroids.len(), 5, "{:?}", initializer);
+        }
+
+        for initializer in [
+            Initializer::KMeansPlusPlus,
+            Initializer::Random,
+            Initializer::KMeansParallel,
+            Initializer::GreedyKMeansPlusPlus { trials: 0 },
+        ] {
+            let centroids = initializer.initialize_weighted_centroids(&data, &single, 5, Some(1));
+            assert_eq!(centroids.len(), 5, "{:?}", initializer);
+            let distinct = centroids
+                .iter()
+                .enumerate()
+                .all(|(i, centroid)| !centroids[..i].contains(centroid));
+            assert!(distinct, "{:?}", initializer);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::distance::euclidean_distance_squared;
+use crate::kmeans::initializer::get_seedable_rng;
+use crate::kmeans::types::{Assignments, Centroids};
+use crate::kmeans::utils::find_closest_centroid;
+use crate::types::VectorExt;
+use rand::seq::index::sample;
+use std::collections::HashMap;
+
+// Number of samples CLARA runs PAM on
+const CLARA_SAMPLES: usize = 5;
+
+// Sample size recommended by Kaufman & Rousseeuw. Images with fewer pixels are
+// clustered whole.
+fn clara_sample_size(k: usize) -> usize {
+    40 + 2 * k
+}
+
+// Distinct colors and how many times each occurs
+struct WeightedPoints<T> {
+    points: Vec<T>,
+    weights: Vec<f32>,
+    indices: HashMap<[u32; 3], usize>,
+}
+
+impl<T: VectorExt> WeightedPoints<T> {
+    fn new() -> Self {
+        WeightedPoints {
+            points: Vec::new(),
+            weights: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, pixel: &T) {
+        let key = [pixel[0].to_bits(), pixel[1].to_bits(), pixel[2].to_bits()];
+        match self.indices.get(&key) {
+            Some(&index) => self.weights[index] += 1.0,
+            None => {
+                self.indices.insert(key, self.points.len());
+                self.points.push(*pixel);
+                self.weights.push(1.0);
+            }
+        }
+    }
+
+    fn contains(&self, pixel: &T) -> bool {
+        let key = [pixel[0].to_bits(), pixel[1].to_bits(), pixel[2].to_bits()];
+        self.indices.contains_key(&key)
+    }
+}
+
+// Every point's nearest medoid, and its distances to the nearest two
+struct NearestMedoids {
+    first: Vec<usize>,
+    first_distance: Vec<f32>,
+    second_distance: Vec<f32>,
+}
+
+impl NearestMedoids {
+    fn new(distances: &[f32], n: usize, medoids: &[usize]) -> Self {
+        let mut nearest = NearestMedoids {
+            first: vec![0; n],
+            first_distance: vec![f32::MAX; n],
+            second_distance: vec![f32::MAX; n],
+        };
+        for o in 0..n {
+            for (i, &medoid) in medoids.iter().enumerate() {
+                let distance = distances[o * n + medoid];
+                if distance < nearest.first_distance[o] {
+                    nearest.second_distance[o] = nearest.first_distance[o];
+                    nearest.first_distance[o] = distance;
+                    nearest.first[o] = i;
+                } else if distance < nearest.second_distance[o] {
+                    nearest.second_distance[o] = distance;
+                }
+            }
+        }
+        nearest
+    }
+}
+
+fn distance<T: VectorExt>(a: &T, b: &T) -> f32 {
+    euclidean_distance_squared(a, b).sqrt().0
+}
+
+// Partitioning Around Medoids (Kaufman & Rousseeuw, 1990) on weighted points, with the
+// O(n) swap evaluation of FastPAM1 (Schubert & Rousseeuw, 2019). Improving swaps are
+// applied as soon as they are found. Returns the indices of the medoids.
+fn pam<T: VectorExt>(points: &[T], weights: &[f32], k: usize, max_iterations: usize) -> Vec<usize> {
+    let n = points.len();
+    let k = k.min(n);
+    let distances: Vec<f32> = points
+        .iter()
+        .flat_map(|a| points.iter().map(move |b| distance(a, b)))
+        .collect();
+    let d = |a: usize, b: usize| distances[a * n + b];
+
+    // BUILD: greedily add the point that lowers the total distance the most
+    let mut medoids: Vec<usize> = Vec::with_capacity(k);
+    let mut closest = vec![f32::MAX; n];
+    while medoids.len() < k {
+        let (best, _) = (0..n)
+            .filter(|candidate| !medoids.contains(candidate))
+            .map(|candidate| {
+                let cost: f64 = (0..n)
+                    .map(|o| (weights[o] * closest[o].min(d(o, candidate))) as f64)
+                    .sum();
+                (candidate, cost)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        medoids.push(best);
+        for (o, closest) in closest.iter_mut().enumerate() {
+            *closest = closest.min(d(o, best));
+        }
+    }
+
+    // SWAP
+    let mut nearest = NearestMedoids::new(&distances, n, &medoids);
+    let mut deltas = vec![0.0f64; k];
+    for _ in 0..max_iterations {
+        let mut improved = false;
+        for candidate in 0..n {
+            if medoids.contains(&candidate) {
+                continue;
+            }
+
+            // Change in total distance when swapping each medoid for the candidate.
+            // Points keep their nearest medoid unless it's removed, and move to the
+            // candidate whenever it is closer.
+            let mut shared = 0.0f64;
+            deltas.fill(0.0);
+            for o in 0..n {
+                let to_candidate = d(o, candidate);
+                let weight = weights[o] as f64;
+                let kept = (to_candidate - nearest.first_distance[o]).min(0.0) as f64;
+                let removed = (to_candidate.min(nearest.second_distance[o])
+                    - nearest.first_distance[o]) as f64;
+                shared += weight * kept;
+                deltas[nearest.first[o]] += weight * (removed - kept);
+            }
+
+            let (medoid, delta) = deltas
+                .iter()
+                .enumerate()
+                .min_by(|a, b| a.1.total_cmp(b.1))
+                .unwrap();
+            if shared + delta < -1e-6 {
+                medoids[medoid] = candidate;
+                nearest = NearestMedoids::new(&distances, n, &medoids);
+                improved = true;
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    medoids
+}
+
+fn total_distance<T: VectorExt>(data: &[T], medoids: &[T]) -> f64 {
+    data.iter()
+        .map(|pixel| distance(pixel, &medoids[find_closest_centroid(pixel, medoids)]) as f64)
+        .sum()
+}
+
+// K-medoids: like k-means, but every centroid is one of the input pixels.
+// Small images run PAM on all of their pixels. Larger ones use CLARA (Kaufman &
+// Rousseeuw, 1990): PAM runs on several random samples, each seeded with the best
+// medoids so far, and the medoids with the lowest total distance over the whole
+// image win.
+pub fn kmeans_medoids<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+) -> (Assignments, Centroids<T>) {
+    if data.is_empty() || config.k == 0 {
+        return (vec![0; data.len()], Vec::new());
+    }
+
+    let sample_size = clara_sample_size(config.k);
+    let mut medoids: Centroids<T> = if data.len() <= sample_size {
+        let mut points = WeightedPoints::new();
+        data.iter().for_each(|pixel| points.insert(pixel));
+        pam(
+            &points.points,
+            &points.weights,
+            config.k,
+            config.max_iterations,
+        )
+        .iter()
+        .map(|&medoid| points.points[medoid])
+        .collect()
+    } else {
+        let mut rng = get_seedable_rng(config.seed);
+        let mut best: Option<(f64, Centroids<T>)> = None;
+        for _ in 0..CLARA_SAMPLES {
+            let mut points = WeightedPoints::new();
+            if let Some((_, medoids)) = &best {
+                medoids.iter().for_each(|medoid| points.insert(medoid));
+            }
+            for index in sample(&mut rng, data.len(), sample_size) {
+                points.insert(&data[index]);
+            }
+            // A sample of a low-color image can have fewer than k colors
+            for pixel in data {
+                if points.points.len() >= config.k {
+                    break;
+                }
+                if !points.contains(pixel) {
+                    points.insert(pixel);
+                }
+            }
+
+            let medoids: Centroids<T> = pam(
+                &points.points,
+                &points.weights,
+                config.k,
+                config.max_iterations,
+            )
+            .iter()
+            .map(|&medoid| points.points[medoid])
+            .collect();
+            let cost = total_distance(data, &medoids);
+            if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+                best = Some((cost, medoids));
+            }
+        }
+        best.unwrap().1
+    };
+
+    // Order doesn't matter, but keep it stable for callers comparing runs
+    medoids.sort_by(|a, b| {
+        (0..3)
+            .map(|channel| a[channel].total_cmp(&b[channel]))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let assignments = data
+        .iter()
+        .map(|pixel| find_closest_centroid(pixel, &medoids))
+        .collect();
+    (assignments, medoids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec4;
+
+    #[test]
+    fn test_medoids_are_input_pixels() {
+        // Large enough for CLARA to sample
+        let data: Vec<Vec4> = (0..3000)
+            .map(|i| {
+                let i = i as f32;
+                [
+                    (i * 7.0) % 256.0,
+                    (i * 13.0) % 256.0,
+                    (i * 29.0) % 256.0,
+                    255.0,
+                ]
+            })
+            .collect();
+        let config = KMeansConfig {
+            k: 8,
+            seed: Some(6),
+            ..Default::default()
+        };
+
+        let (assignments, medoids) = kmeans_medoids(&data, &config);
+
+        assert_eq!(medoids.len(), 8);
+        assert!(medoids.iter().all(|medoid| data.contains(medoid)));
+        assert_eq!(assignments.len(), data.len());
+    }
+
+    #[test]
+    fn test_pam_picks_cluster_centers() {
+        let data: Vec<Vec4> = vec![
+            [0.0, 0.0, 0.0, 255.0],
+            [10.0, 0.0, 0.0, 255.0],
+            [20.0, 0.0, 0.0, 255.0],
+            [200.0, 200.0, 200.0, 255.0],
+            [210.0, 200.0, 200.0, 255.0],
+            [220.0, 200.0, 200.0, 255.0],
+        ];
+        let config = KMeansConfig {
+            k: 2,
+            ..Default::default()
+        };
+
+        let (assignments, medoids) = kmeans_medoids(&data, &config);
+
+        assert_eq!(
+            medoids,
+            vec![[10.0, 0.0, 0.0, 255.0], [210.0, 200.0, 200.0, 255.0]]
+        );
+        assert_eq!(assignments, vec![0, 0, 0, 1, 1, 1]);
+    }
+}
@@ -1,11 +1,37 @@
 use crate::kmeans::config::KMeansConfig;
-use crate::kmeans::utils::{find_closest_centroid, has_converged};
+use crate::kmeans::utils::{
+    fill_empty_clusters, find_closest_centroid, finish_empty_clusters, has_converged,
+};
 use crate::types::VectorExt;
 
 pub fn kmeans_lloyd<T: VectorExt>(data: &[T], config: &KMeansConfig) -> (Vec<usize>, Vec<T>) {
-    let mut centroids = config
+    let centroids = config
         .initializer
         .initialize_centroids(data, config.k, config.seed);
+    lloyd(data, None, centroids, config)
+}
+
+// Lloyd's algorithm on points that each count as `weight` copies of themselves
+pub fn kmeans_lloyd_weighted<T: VectorExt>(
+    data: &[T],
+    weights: &[f32],
+    config: &KMeansConfig,
+) -> (Vec<usize>, Vec<T>) {
+    let centroids =
+        config
+            .initializer
+            .initialize_weighted_centroids(data, weights, config.k, config.seed);
+    lloyd(data, Some(weights), centroids, config)
+}
+
+// Without weights, every point counts once
+fn lloyd<T: VectorExt>(
+    data: &[T],
+    weights: Option<&[f32]>,
+    mut centroids: Vec<T>,
+    config: &KMeansConfig,
+) -> (Vec<usize>, Vec<T>) {
+    let weight = |i: usize| weights.map_or(1.0, |weights| weights[i]);
     let mut new_centroids: Vec<T> = centroids.clone();
 
     let mut clusters = vec![Vec::new(); config.k];
@@ -22,6 +48,12 @@ pub fn kmeans_lloyd<T: VectorExt>(data: &[T], config: &KMeansConfig) -> (Vec<usi
                 assignments[i] = closest_centroid;
             }
         }
+        fill_empty_clusters(
+            data,
+            &mut assignments,
+            &centroids,
+            config.empty_cluster_policy,
+        );
 
         clusters.iter_mut().for_each(|cluster| cluster.clear());
         assignments.iter().enumerate().for_each(|(i, &cluster)| {
@@ -31,27 +63,32 @@ pub fn kmeans_lloyd<T: VectorExt>(data: &[T], config: &KMeansConfig) -> (Vec<usi
         // Update centroids and check for convergence
         clusters
             .iter()
-            .zip(new_centroids.iter_mut())
-            .for_each(|(cluster, new_centroid)| {
-                if cluster.is_empty() {
-                    return; // centroid can't move if there are no points
-                }
-
+            .zip(centroids.iter().zip(new_centroids.iter_mut()))
+            .for_each(|(cluster, (centroid, new_centroid))| {
                 let mut sum_r = 0.0;
                 let mut sum_g = 0.0;
                 let mut sum_b = 0.0;
-                let num_pixels = cluster.len() as f32;
+                let mut total_weight = 0.0;
 
                 for &idx in cluster {
                     let pixel = &data[idx];
-                    sum_r += pixel[0];
-                    sum_g += pixel[1];
-                    sum_b += pixel[2];
+                    let weight = weight(idx);
+                    sum_r += weight * pixel[0];
+                    sum_g += weight * pixel[1];
+                    sum_b += weight * pixel[2];
+                    total_weight += weight;
+                }
+
+                // Empty under Keep/Drop, or when repair found nothing to move. Clusters
+                // of zero-weight points have no mean, so both keep their centroid.
+                if total_weight <= 0.0 {
+                    *new_centroid = *centroid;
+                    return;
                 }
 
-                new_centroid[0] = sum_r / num_pixels;
-                new_centroid[1] = sum_g / num_pixels;
-                new_centroid[2] = sum_b / num_pixels;
+                new_centroid[0] = sum_r / total_weight;
+                new_centroid[1] = sum_g / total_weight;
+                new_centroid[2] = sum_b / total_weight;
             });
         converged = has_converged(&centroids, &new_centroids, config.tolerance);
         // Swap the centroids and new_centroid. We'll update the new centroids again before
@@ -60,5 +97,5 @@ pub fn kmeans_lloyd<T: VectorExt>(data: &[T], config: &KMeansConfig) -> (Vec<usi
         iterations += 1;
     }
 
-    (assignments, centroids)
+    finish_empty_clusters(assignments, centroids, config.empty_cluster_policy)
 }
@@ -0,0 +1,205 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::types::{Assignments, Centroids};
+use crate::kmeans::utils::find_closest_centroid;
+use crate::types::VectorExt;
+
+const COLOR_CHANNELS: usize = 3;
+
+// A box is a contiguous range of the shared index buffer, so splitting
+// a box is just sorting its slice and cutting it in two.
+struct ColorBox {
+    start: usize,
+    end: usize,
+    // (channel, range) of the widest channel in the box
+    widest_channel: usize,
+    range: f32,
+    // Total weight of the points in the box
+    weight: f32,
+}
+
+impl ColorBox {
+    fn new<T: VectorExt>(
+        data: &[T],
+        weights: &[f32],
+        indices: &[usize],
+        start: usize,
+        end: usize,
+    ) -> Self {
+        let mut min = [f32::MAX; COLOR_CHANNELS];
+        let mut max = [f32::MIN; COLOR_CHANNELS];
+        for &idx in &indices[start..end] {
+            for channel in 0..COLOR_CHANNELS {
+                min[channel] = min[channel].min(data[idx][channel]);
+                max[channel] = max[channel].max(data[idx][channel]);
+            }
+        }
+
+        let (widest_channel, range) = (0..COLOR_CHANNELS)
+            .map(|channel| (channel, max[channel] - min[channel]))
+            .fold((0, f32::MIN), |best, candidate| {
+                if candidate.1 > best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            });
+
+        Self {
+            start,
+            end,
+            widest_channel,
+            range,
+            weight: indices[start..end].iter().map(|&idx| weights[idx]).sum(),
+        }
+    }
+}
+
+// Deterministic median-cut palette (Heckbert, 1982). The box with the widest color
+// range is split at the median of that channel until there are k boxes or every box
+// holds a single color. Each palette entry is the mean of its box.
+pub fn median_cut<T: VectorExt>(data: &[T], k: usize) -> Centroids<T> {
+    weighted_median_cut(data, &vec![1.0; data.len()], k)
+}
+
+// Median cut on points that each count as `weight` copies of themselves. Boxes are cut
+// at the weighted median, and palette entries are weighted means.
+pub fn weighted_median_cut<T: VectorExt>(data: &[T], weights: &[f32], k: usize) -> Centroids<T> {
+    if data.is_empty() || k == 0 {
+        return Vec::new();
+    }
+
+    let mut indices: Vec<usize> = (0..data.len()).collect();
+    let mut boxes = vec![ColorBox::new(data, weights, &indices, 0, data.len())];
+
+    while boxes.len() < k {
+        // Ties go to the more populated box
+        let Some((box_index, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, color_box)| color_box.range > 0.0)
+            .max_by(|(_, a), (_, b)| {
+                a.range
+                    .partial_cmp(&b.range)
+                    .unwrap()
+                    .then(a.weight.total_cmp(&b.weight))
+            })
+        else {
+            break; // every box is a single color
+        };
+
+        let color_box = boxes.swap_remove(box_index);
+        let channel = color_box.widest_channel;
+        let slice = &mut indices[color_box.start..color_box.end];
+        slice.sort_by(|&a, &b| data[a][channel].partial_cmp(&data[b][channel]).unwrap());
+
+        // Move the cut off the median until it sits between two different values,
+        // so that neither half is empty. One exists because the range is non-zero.
+        // Positions are measured in weight, so a point of weight w acts like w copies.
+        let median = (color_box.weight / 2.0).floor();
+        let mut below = vec![0.0f32; slice.len() + 1];
+        for (i, &idx) in slice.iter().enumerate() {
+            below[i + 1] = below[i] + weights[idx];
+        }
+        let differs = |i: usize| data[slice[i - 1]][channel] < data[slice[i]][channel];
+        let first_above = below.partition_point(|&weight| weight < median).max(1);
+        let cut = (first_above..slice.len())
+            .find(|&i| differs(i))
+            .into_iter()
+            .chain((1..first_above).rev().find(|&i| differs(i)))
+            .min_by(|&a, &b| {
+                (below[a] - median)
+                    .abs()
+                    .total_cmp(&(below[b] - median).abs())
+            })
+            .expect("a box with a non-zero range can always be split");
+
+        let split = color_box.start + cut;
+        boxes.push(ColorBox::new(
+            data,
+            weights,
+            &indices,
+            color_box.start,
+            split,
+        ));
+        boxes.push(ColorBox::new(data, weights, &indices, split, color_box.end));
+    }
+
+    // Keep the output order stable regardless of the order boxes were split in
+    boxes.sort_by_key(|color_box| color_box.start);
+    boxes
+        .iter()
+        .map(|color_box| {
+            let sum = indices[color_box.start..color_box.end]
+                .iter()
+                .fold(T::zero(), |sum, &idx| {
+                    sum.add(&data[idx].mul_scalar(weights[idx]))
+                });
+            sum.div_scalar(color_box.weight)
+        })
+        .collect()
+}
+
+pub fn quantize_median_cut<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+) -> (Assignments, Centroids<T>) {
+    let centroids = median_cut(data, config.k);
+    let assignments = data
+        .iter()
+        .map(|pixel| find_closest_centroid(pixel, &centroids))
+        .collect();
+    (assignments, centroids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec4;
+
+    #[test]
+    fn test_median_cut_splits_widest_channel() {
+        let data: Vec<Vec4> = vec![
+            [0.0, 0.0, 0.0, 255.0],
+            [10.0, 0.0, 0.0, 255.0],
+            [200.0, 0.0, 0.0, 255.0],
+            [210.0, 0.0, 0.0, 255.0],
+        ];
+
+        let centroids = median_cut(&data, 2);
+
+        assert_eq!(
+            centroids,
+            vec![[5.0, 0.0, 0.0, 255.0], [205.0, 0.0, 0.0, 255.0]]
+        );
+    }
+
+    #[test]
+    fn test_median_cut_stops_at_distinct_colors() {
+        let data: Vec<Vec4> = vec![
+            [50.0, 50.0, 50.0, 255.0],
+            [50.0, 50.0, 50.0, 255.0],
+            [50.0, 50.0, 50.0, 255.0],
+            [90.0, 50.0, 50.0, 255.0],
+        ];
+
+        let centroids = median_cut(&data, 4);
+
+        assert_eq!(centroids.len(), 2);
+    }
+
+    #[test]
+    fn test_median_cut_is_independent_of_input_order() {
+        let data: Vec<Vec4> = (0..64)
+            .map(|i| [(i * 37 % 256) as f32, (i * 11 % 256) as f32, i as f32, 0.0])
+            .collect();
+        let mut reversed = data.clone();
+        reversed.reverse();
+
+        let mut a = median_cut(&data, 8);
+        let mut b = median_cut(&reversed, 8);
+        a.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        b.sort_by(|x, y| x.partial_cmp(y).unwrap());
+
+        assert_eq!(a, b);
+    }
+}
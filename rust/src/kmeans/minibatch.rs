@@ -0,0 +1,154 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::initializer::get_seedable_rng;
+use crate::kmeans::types::{Assignments, Centroids};
+use crate::kmeans::utils::{
+    cluster_sums, fill_empty_clusters, find_closest_centroid, finish_empty_clusters, has_converged,
+};
+use crate::types::VectorExt;
+use rand::seq::index::sample;
+use rand::Rng;
+
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
+// The centroids are initialized on a random sample of this many batches, like
+// scikit-learn's init_size, so that initialization doesn't scale with the image either
+const INIT_BATCHES: usize = 3;
+
+// Mini-batch k-means (Sculley, 2010). Each iteration draws a random batch and nudges
+// the centroids towards it with a per-centroid learning rate of 1 / (points seen so far),
+// so the total work is bounded by max_iterations * batch_size regardless of image size.
+pub fn kmeans_minibatch<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+    batch_size: usize,
+) -> (Assignments, Centroids<T>) {
+    let mut rng = get_seedable_rng(config.seed);
+    let batch_size = batch_size.max(1);
+
+    let init_size = INIT_BATCHES * batch_size;
+    let mut centroids = if data.len() > init_size {
+        let init_sample: Vec<T> = sample(&mut rng, data.len(), init_size)
+            .iter()
+            .map(|index| data[index])
+            .collect();
+        config
+            .initializer
+            .initialize_centroids(&init_sample, config.k, config.seed)
+    } else {
+        config
+            .initializer
+            .initialize_centroids(data, config.k, config.seed)
+    };
+
+    if data.is_empty() || centroids.is_empty() {
+        return (vec![0; data.len()], centroids);
+    }
+
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut batch_assignments = Vec::with_capacity(batch_size);
+    let mut centroid_counts = vec![0usize; centroids.len()];
+    let mut previous_centroids = centroids.clone();
+
+    for _ in 0..config.max_iterations {
+        batch.clear();
+        batch.extend((0..batch_size).map(|_| &data[rng.gen_range(0..data.len())]));
+
+        // Assignments are cached against the centroids from the start of the batch
+        batch_assignments.clear();
+        batch_assignments.extend(
+            batch
+                .iter()
+                .map(|pixel| find_closest_centroid(*pixel, &centroids)),
+        );
+
+        for (pixel, &cluster) in batch.iter().zip(batch_assignments.iter()) {
+            centroid_counts[cluster] += 1;
+            // c <- c + (x - c) / n is the running mean with learning rate 1 / n
+            let step = pixel
+                .sub(&centroids[cluster])
+                .div_scalar(centroid_counts[cluster] as f32);
+            centroids[cluster] = centroids[cluster].add(&step);
+        }
+
+        if has_converged(&previous_centroids, &centroids, config.tolerance) {
+            break;
+        }
+        previous_centroids.copy_from_slice(&centroids);
+    }
+
+    let mut assignments: Assignments = data
+        .iter()
+        .map(|pixel| find_closest_centroid(pixel, &centroids))
+        .collect();
+
+    // Empty clusters are repaired once, on the full assignment. Clusters that get pixels
+    // move to their mean, the rest keep what they learned from the batches.
+    let moved = fill_empty_clusters(
+        data,
+        &mut assignments,
+        &centroids,
+        config.empty_cluster_policy,
+    );
+    if !moved.is_empty() {
+        let (sums, counts) = cluster_sums(data, &assignments, centroids.len());
+        for i in moved {
+            let cluster = assignments[i];
+            centroids[cluster] = sums[cluster].div_scalar(counts[cluster] as f32);
+        }
+    }
+
+    finish_empty_clusters(assignments, centroids, config.empty_cluster_policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kmeans::config::KMeansAlgorithm;
+    use crate::types::Vec3;
+
+    fn two_blobs() -> Vec<Vec3> {
+        (0..500)
+            .map(|i| {
+                let offset = (i % 7) as f32;
+                if i % 2 == 0 {
+                    [10.0 + offset, 10.0 + offset, 10.0 + offset]
+                } else {
+                    [200.0 + offset, 200.0 + offset, 200.0 + offset]
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_minibatch_is_reproducible_with_seed() {
+        let data = two_blobs();
+        let config = KMeansConfig {
+            k: 2,
+            algorithm: KMeansAlgorithm::MiniBatch { batch_size: 32 },
+            seed: Some(42),
+            ..Default::default()
+        };
+
+        let (assignments_a, centroids_a) = kmeans_minibatch(&data, &config, 32);
+        let (assignments_b, centroids_b) = kmeans_minibatch(&data, &config, 32);
+
+        assert_eq!(assignments_a, assignments_b);
+        assert_eq!(centroids_a, centroids_b);
+    }
+
+    #[test]
+    fn test_minibatch_separates_blobs() {
+        let data = two_blobs();
+        let config = KMeansConfig {
+            k: 2,
+            seed: Some(7),
+            ..Default::default()
+        };
+
+        let (assignments, _) = kmeans_minibatch(&data, &config, 64);
+
+        assert_ne!(assignments[0], assignments[1]);
+        for (i, &cluster) in assignments.iter().enumerate() {
+            assert_eq!(cluster, assignments[i % 2]);
+        }
+    }
+}
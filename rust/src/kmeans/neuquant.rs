@@ -0,0 +1,219 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::types::{Assignments, Centroids};
+use crate::kmeans::utils::find_closest_centroid;
+use crate::types::VectorExt;
+
+// NeuQuant (Dekker, 1994): a one-dimensional self-organizing map of k neurons is
+// trained on a sample of the image, and the neurons become the palette.
+// This is a floating point port of the reference implementation, so the constants
+// below are the reference's fixed point constants converted to plain ratios.
+
+pub const DEFAULT_SAMPLE_FACTOR: usize = 10;
+// Sampling factors outside this range are clamped, as in the reference implementation
+pub const MAX_SAMPLE_FACTOR: usize = 30;
+
+// Number of learning cycles, each of which shrinks the learning rate and radius
+const LEARNING_CYCLES: usize = 100;
+// Images smaller than this are learned from every pixel
+const MIN_SAMPLED_PIXELS: usize = 503;
+// The sample walks the image in prime strides to avoid aliasing against image patterns
+const PRIMES: [usize; 4] = [499, 491, 487, 503];
+
+// Frequency and bias learning rates for the "conscience" that keeps neurons from
+// winning more than their share of samples
+const BETA: f64 = 1.0 / 1024.0;
+const GAMMA: f64 = 1024.0;
+
+// The neighbourhood radius starts at k / 8 neurons and decays by 1/30 every cycle
+const INITIAL_RADIUS_DIVISOR: f64 = 8.0;
+const RADIUS_DECREASE: f64 = 30.0;
+
+struct Network {
+    neurons: Vec<[f64; 3]>,
+    frequencies: Vec<f64>,
+    biases: Vec<f64>,
+}
+
+impl Network {
+    fn new(size: usize) -> Self {
+        // Neurons start spread evenly along the gray diagonal
+        let neurons = (0..size)
+            .map(|i| {
+                let value = 256.0 * i as f64 / size as f64;
+                [value; 3]
+            })
+            .collect();
+        Network {
+            neurons,
+            frequencies: vec![1.0 / size as f64; size],
+            biases: vec![0.0; size],
+        }
+    }
+
+    // Find the winning neuron, biased against neurons that have been winning too often
+    fn contest(&mut self, color: &[f64; 3]) -> usize {
+        let mut best_distance = f64::MAX;
+        let mut best_biased_distance = f64::MAX;
+        let mut best_position = 0;
+        let mut best_biased_position = 0;
+
+        for (i, neuron) in self.neurons.iter().enumerate() {
+            let distance: f64 = neuron
+                .iter()
+                .zip(color.iter())
+                .map(|(n, c)| (n - c).abs())
+                .sum();
+            if distance < best_distance {
+                best_distance = distance;
+                best_position = i;
+            }
+
+            let biased_distance = distance - self.biases[i];
+            if biased_distance < best_biased_distance {
+                best_biased_distance = biased_distance;
+                best_biased_position = i;
+            }
+
+            let beta_frequency = self.frequencies[i] * BETA;
+            self.frequencies[i] -= beta_frequency;
+            self.biases[i] += beta_frequency * GAMMA;
+        }
+
+        self.frequencies[best_position] += BETA;
+        self.biases[best_position] -= BETA * GAMMA;
+        best_biased_position
+    }
+
+    fn move_neuron(&mut self, neuron: usize, rate: f64, color: &[f64; 3]) {
+        for (n, c) in self.neurons[neuron].iter_mut().zip(color.iter()) {
+            *n -= rate * (*n - c);
+        }
+    }
+
+    // Pull the neighbours of the winner towards the sample, less so the farther they are
+    fn move_neighbours(&mut self, neuron: usize, radius: usize, rates: &[f64], color: &[f64; 3]) {
+        for (offset, &rate) in rates.iter().enumerate().take(radius).skip(1) {
+            if neuron + offset < self.neurons.len() {
+                self.move_neuron(neuron + offset, rate, color);
+            }
+            if offset <= neuron {
+                self.move_neuron(neuron - offset, rate, color);
+            }
+        }
+    }
+}
+
+fn neighbourhood_rates(alpha: f64, radius: usize) -> Vec<f64> {
+    let radius_squared = (radius * radius) as f64;
+    (0..radius.max(1))
+        .map(|i| alpha * (radius_squared - (i * i) as f64) / radius_squared)
+        .collect()
+}
+
+pub fn neuquant<T: VectorExt>(data: &[T], k: usize, sample_factor: usize) -> Centroids<T> {
+    if data.is_empty() || k == 0 {
+        return Vec::new();
+    }
+
+    let sample_factor = if data.len() < MIN_SAMPLED_PIXELS {
+        1
+    } else {
+        sample_factor.clamp(1, MAX_SAMPLE_FACTOR)
+    };
+
+    let mut network = Network::new(k);
+
+    let alpha_decrease = 30.0 + ((sample_factor - 1) / 3) as f64;
+    let sampled_pixels = data.len() / sample_factor;
+    let cycle_length = (sampled_pixels / LEARNING_CYCLES).max(1);
+    let step = PRIMES
+        .iter()
+        .copied()
+        .find(|prime| !data.len().is_multiple_of(*prime))
+        .unwrap_or(1);
+
+    let mut alpha = 1.0;
+    let mut radius = k as f64 / INITIAL_RADIUS_DIVISOR;
+    let shrink_radius = |radius: f64| match radius as usize {
+        0 | 1 => 0,
+        r => r,
+    };
+    let mut neighbourhood = shrink_radius(radius);
+    let mut rates = neighbourhood_rates(alpha, neighbourhood);
+
+    let mut position = 0;
+    for i in 1..=sampled_pixels {
+        let pixel = &data[position];
+        let color = [pixel[0] as f64, pixel[1] as f64, pixel[2] as f64];
+
+        let winner = network.contest(&color);
+        network.move_neuron(winner, alpha, &color);
+        if neighbourhood > 0 {
+            network.move_neighbours(winner, neighbourhood, &rates, &color);
+        }
+
+        position = (position + step) % data.len();
+
+        if i % cycle_length == 0 {
+            alpha -= alpha / alpha_decrease;
+            radius -= radius / RADIUS_DECREASE;
+            neighbourhood = shrink_radius(radius);
+            rates = neighbourhood_rates(alpha, neighbourhood);
+        }
+    }
+
+    network
+        .neurons
+        .iter()
+        .map(|neuron| {
+            let mut centroid = T::zero();
+            for (channel, value) in neuron.iter().enumerate() {
+                centroid[channel] = value.clamp(0.0, 255.0) as f32;
+            }
+            centroid
+        })
+        .collect()
+}
+
+pub fn quantize_neuquant<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+    sample_factor: usize,
+) -> (Assignments, Centroids<T>) {
+    let centroids = neuquant(data, config.k, sample_factor);
+    let assignments = data
+        .iter()
+        .map(|pixel| find_closest_centroid(pixel, &centroids))
+        .collect();
+    (assignments, centroids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec4;
+
+    #[test]
+    fn test_neuquant_learns_dominant_colors() {
+        let data: Vec<Vec4> = (0..5000)
+            .map(|i| match i % 3 {
+                0 => [250.0, 10.0, 10.0, 255.0],
+                1 => [10.0, 250.0, 10.0, 255.0],
+                _ => [10.0, 10.0, 250.0, 255.0],
+            })
+            .collect();
+
+        let palette = neuquant(&data, 8, DEFAULT_SAMPLE_FACTOR);
+        assert_eq!(palette.len(), 8);
+
+        for color in [
+            [250.0, 10.0, 10.0, 0.0],
+            [10.0, 250.0, 10.0, 0.0],
+            [10.0, 10.0, 250.0, 0.0],
+        ] {
+            let closest = &palette[find_closest_centroid(&color, &palette)];
+            let error: f32 = (0..3).map(|c| (closest[c] - color[c]).abs()).sum();
+            assert!(error < 15.0, "{:?} is not close to {:?}", closest, color);
+        }
+    }
+}
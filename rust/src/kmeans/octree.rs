@@ -0,0 +1,230 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::types::{Assignments, Centroids};
+use crate::kmeans::utils::find_closest_centroid;
+use crate::types::VectorExt;
+
+// Leaves live at depth 8, one level per bit of each 8-bit channel
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    children: [Option<usize>; 8],
+    is_leaf: bool,
+    // Number of pixels inserted through this node
+    pixel_count: usize,
+    // Only populated for leaves
+    sums: [f64; 3],
+}
+
+// Octree color quantizer (Gervautz & Purgathofer, 1988).
+// Pixels are inserted one at a time, and whenever there are more than `max_colors`
+// leaves the least populated node at the deepest level is folded into a single leaf.
+// Memory use is bounded by `max_colors`, not by the number of pixels.
+#[derive(Debug, Clone)]
+pub struct Octree {
+    nodes: Vec<Node>,
+    // Nodes freed by merges, reused before the arena grows
+    free_nodes: Vec<usize>,
+    // Internal nodes at each depth, candidates for merging
+    reducible: [Vec<usize>; MAX_DEPTH],
+    leaf_count: usize,
+    max_colors: usize,
+}
+
+impl Octree {
+    pub fn new(max_colors: usize) -> Self {
+        let mut reducible: [Vec<usize>; MAX_DEPTH] = Default::default();
+        reducible[0].push(0);
+        Octree {
+            nodes: vec![Node::default()],
+            free_nodes: Vec::new(),
+            reducible,
+            leaf_count: 0,
+            max_colors: max_colors.max(1),
+        }
+    }
+
+    pub fn insert<T: VectorExt>(&mut self, pixel: &T) {
+        let color = [
+            pixel[0].clamp(0.0, 255.0),
+            pixel[1].clamp(0.0, 255.0),
+            pixel[2].clamp(0.0, 255.0),
+        ];
+        let bits = color.map(|channel| channel as u8);
+
+        let mut node = 0;
+        for depth in 0..=MAX_DEPTH {
+            self.nodes[node].pixel_count += 1;
+
+            if depth == MAX_DEPTH || self.nodes[node].is_leaf {
+                let leaf = &mut self.nodes[node];
+                if !leaf.is_leaf {
+                    leaf.is_leaf = true;
+                    self.leaf_count += 1;
+                }
+                for (sum, &channel) in leaf.sums.iter_mut().zip(color.iter()) {
+                    *sum += channel as f64;
+                }
+                break;
+            }
+
+            let shift = MAX_DEPTH - 1 - depth;
+            let child_index = (((bits[0] >> shift) & 1) << 2
+                | ((bits[1] >> shift) & 1) << 1
+                | ((bits[2] >> shift) & 1)) as usize;
+
+            node = match self.nodes[node].children[child_index] {
+                Some(child) => child,
+                None => {
+                    let child = self.allocate_node();
+                    self.nodes[node].children[child_index] = Some(child);
+                    if depth + 1 < MAX_DEPTH {
+                        self.reducible[depth + 1].push(child);
+                    }
+                    child
+                }
+            };
+        }
+
+        while self.leaf_count > self.max_colors {
+            if !self.reduce() {
+                break;
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaf_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    // The mean color of every leaf. Only the first three channels are filled in.
+    pub fn palette<T: VectorExt>(&self) -> Centroids<T> {
+        let mut palette = Vec::with_capacity(self.leaf_count);
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if node.is_leaf {
+                let mut color = T::zero();
+                for (channel, sum) in node.sums.iter().enumerate() {
+                    color[channel] = (sum / node.pixel_count as f64) as f32;
+                }
+                palette.push(color);
+            } else {
+                // Reverse so that the palette comes out in child order
+                stack.extend(node.children.iter().rev().flatten());
+            }
+        }
+        palette
+    }
+
+    fn allocate_node(&mut self) -> usize {
+        if let Some(node) = self.free_nodes.pop() {
+            self.nodes[node] = Node::default();
+            node
+        } else {
+            self.nodes.push(Node::default());
+            self.nodes.len() - 1
+        }
+    }
+
+    // Folds the least populated node at the deepest level into a leaf.
+    // Its children are all leaves, since any internal child would be deeper.
+    fn reduce(&mut self) -> bool {
+        let Some(depth) = (0..MAX_DEPTH)
+            .rev()
+            .find(|&depth| !self.reducible[depth].is_empty())
+        else {
+            return false;
+        };
+
+        let candidates = &mut self.reducible[depth];
+        let (position, _) = candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &node)| self.nodes[node].pixel_count)
+            .unwrap();
+        let node = candidates.swap_remove(position);
+
+        let mut sums = [0.0; 3];
+        let mut merged_leaves = 0;
+        for child in self.nodes[node].children.iter().flatten() {
+            let child = &self.nodes[*child];
+            for (sum, child_sum) in sums.iter_mut().zip(child.sums.iter()) {
+                *sum += child_sum;
+            }
+            merged_leaves += 1;
+        }
+
+        let children = std::mem::take(&mut self.nodes[node].children);
+        self.free_nodes.extend(children.iter().flatten());
+
+        let merged = &mut self.nodes[node];
+        merged.is_leaf = true;
+        merged.sums = sums;
+        self.leaf_count = self.leaf_count + 1 - merged_leaves;
+        true
+    }
+}
+
+pub fn quantize_octree<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+) -> (Assignments, Centroids<T>) {
+    let mut octree = Octree::new(config.k);
+    for pixel in data {
+        octree.insert(pixel);
+    }
+
+    let centroids = octree.palette();
+    let assignments = data
+        .iter()
+        .map(|pixel| find_closest_centroid(pixel, &centroids))
+        .collect();
+    (assignments, centroids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec4;
+
+    #[test]
+    fn test_octree_keeps_exact_colors_when_under_limit() {
+        let mut octree = Octree::new(4);
+        octree.insert(&[255.0, 0.0, 0.0, 255.0]);
+        octree.insert(&[0.0, 0.0, 255.0, 255.0]);
+        octree.insert(&[255.0, 0.0, 0.0, 255.0]);
+
+        let mut palette: Vec<Vec4> = octree.palette();
+        palette.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        assert_eq!(
+            palette,
+            vec![[0.0, 0.0, 255.0, 0.0], [255.0, 0.0, 0.0, 0.0]]
+        );
+    }
+
+    #[test]
+    fn test_octree_never_exceeds_max_colors() {
+        let max_colors = 16;
+        let mut octree = Octree::new(max_colors);
+        for i in 0..4096u32 {
+            let pixel: Vec4 = [
+                (i % 16 * 16) as f32,
+                (i / 16 % 16 * 16) as f32,
+                (i / 256 * 16) as f32,
+                255.0,
+            ];
+            octree.insert(&pixel);
+            assert!(octree.len() <= max_colors);
+        }
+
+        let palette: Vec<Vec4> = octree.palette();
+        assert_eq!(palette.len(), octree.len());
+        assert!(palette.len() > max_colors / 8);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::kmeans::types::Centroids;
+use crate::types::VectorExt;
+
+// Deterministic divisive initializers (Su & Dy, 2007). Starting from one cluster, the
+// cluster with the highest SSE is repeatedly cut in two through its mean, until there
+// are k clusters. Var-Part cuts across the channel with the highest variance, PCA-Part
+// across the principal axis. The centroids are the means of the final clusters.
+
+struct Cluster {
+    indices: Vec<usize>,
+    weight: f64,
+    mean: [f64; 3],
+    sse: f64,
+}
+
+impl Cluster {
+    fn new<T: VectorExt>(data: &[T], weights: &[f32], indices: Vec<usize>) -> Self {
+        let mut mean = [0.0; 3];
+        let mut weight = 0.0;
+        for &idx in &indices {
+            weight += weights[idx] as f64;
+            for (channel, sum) in mean.iter_mut().enumerate() {
+                *sum += weights[idx] as f64 * data[idx][channel] as f64;
+            }
+        }
+        let total = if weight > 0.0 { weight } else { 1.0 };
+        mean.iter_mut().for_each(|sum| *sum /= total);
+
+        let sse = indices
+            .iter()
+            .map(|&idx| {
+                weights[idx] as f64
+                    * (0..3)
+                        .map(|channel| (data[idx][channel] as f64 - mean[channel]).powi(2))
+                        .sum::<f64>()
+            })
+            .sum();
+        Cluster {
+            indices,
+            weight,
+            mean,
+            sse,
+        }
+    }
+
+    fn covariance<T: VectorExt>(&self, data: &[T], weights: &[f32]) -> [[f64; 3]; 3] {
+        let mut covariance = [[0.0; 3]; 3];
+        for &idx in &self.indices {
+            let centered: Vec<f64> = (0..3)
+                .map(|channel| data[idx][channel] as f64 - self.mean[channel])
+                .collect();
+            for i in 0..3 {
+                for j in 0..3 {
+                    covariance[i][j] += weights[idx] as f64 * centered[i] * centered[j];
+                }
+            }
+        }
+        covariance
+    }
+}
+
+// Unit vector along the channel with the highest variance
+fn variance_axis(covariance: &[[f64; 3]; 3]) -> [f64; 3] {
+    let channel = (0..3)
+        .max_by(|&a, &b| covariance[a][a].total_cmp(&covariance[b][b]))
+        .unwrap();
+    let mut axis = [0.0; 3];
+    axis[channel] = 1.0;
+    axis
+}
+
+// Principal eigenvector of the covariance, by power iteration from a fixed start
+fn principal_axis(covariance: &[[f64; 3]; 3]) -> [f64; 3] {
+    const ITERATIONS: usize = 50;
+
+    let mut axis = [1.0 / 3f64.sqrt(); 3];
+    for _ in 0..ITERATIONS {
+        let mut next = [0.0; 3];
+        for (i, value) in next.iter_mut().enumerate() {
+            *value = (0..3).map(|j| covariance[i][j] * axis[j]).sum();
+        }
+        let norm = next.iter().map(|v| v * v).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            // The start vector is orthogonal to the spread of the data
+            return variance_axis(covariance);
+        }
+        axis = next.map(|v| v / norm);
+    }
+    axis
+}
+
+fn partition<T: VectorExt>(
+    data: &[T],
+    weights: &[f32],
+    k: usize,
+    axis: fn(&[[f64; 3]; 3]) -> [f64; 3],
+) -> Centroids<T> {
+    if data.is_empty() || k == 0 {
+        return Vec::new();
+    }
+
+    let mut clusters = vec![Cluster::new(data, weights, (0..data.len()).collect())];
+    let mut unsplittable = vec![false];
+    while clusters.len() < k {
+        let Some(target) = (0..clusters.len())
+            .filter(|&i| !unsplittable[i] && clusters[i].sse > 0.0)
+            .max_by(|&a, &b| clusters[a].sse.total_cmp(&clusters[b].sse))
+        else {
+            break; // every cluster is a single color
+        };
+
+        let cluster = &clusters[target];
+        let axis = axis(&cluster.covariance(data, weights));
+        let project = |idx: usize| -> f64 {
+            (0..3)
+                .map(|channel| (data[idx][channel] as f64 - cluster.mean[channel]) * axis[channel])
+                .sum()
+        };
+        let (left, right): (Vec<usize>, Vec<usize>) = cluster
+            .indices
+            .iter()
+            .partition(|&&idx| project(idx) <= 0.0);
+        if left.is_empty() || right.is_empty() {
+            unsplittable[target] = true;
+            continue;
+        }
+
+        clusters[target] = Cluster::new(data, weights, left);
+        clusters.push(Cluster::new(data, weights, right));
+        unsplittable.push(false);
+    }
+
+    clusters
+        .iter()
+        .map(|cluster| {
+            // Means of any extra channels, so that the centroids are complete colors
+            let mut centroid = cluster
+                .indices
+                .iter()
+                .fold(T::zero(), |sum, &idx| {
+                    sum.add(&data[idx].mul_scalar(weights[idx]))
+                })
+                .div_scalar(cluster.weight as f32);
+            for (channel, mean) in cluster.mean.iter().enumerate() {
+                centroid[channel] = *mean as f32;
+            }
+            centroid
+        })
+        .collect()
+}
+
+pub fn variance_partition<T: VectorExt>(data: &[T], k: usize) -> Centroids<T> {
+    partition(data, &vec![1.0; data.len()], k, variance_axis)
+}
+
+pub fn pca_partition<T: VectorExt>(data: &[T], k: usize) -> Centroids<T> {
+    partition(data, &vec![1.0; data.len()], k, principal_axis)
+}
+
+// The partitions on points that each count as `weight` copies of themselves
+pub fn weighted_variance_partition<T: VectorExt>(
+    data: &[T],
+    weights: &[f32],
+    k: usize,
+) -> Centroids<T> {
+    partition(data, weights, k, variance_axis)
+}
+
+pub fn weighted_pca_partition<T: VectorExt>(data: &[T], weights: &[f32], k: usize) -> Centroids<T> {
+    partition(data, weights, k, principal_axis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec3;
+
+    #[test]
+    fn test_pca_partition_cuts_along_the_diagonal() {
+        // Two groups spread along the gray diagonal, where no single channel separates
+        // them better than the others
+        let data: Vec<Vec3> = (0..40)
+            .map(|i| {
+                let base = if i < 20 { 40.0 } else { 200.0 };
+                let jitter = (i % 4) as f32;
+                [base + jitter, base - jitter, base]
+            })
+            .collect();
+
+        let centroids = pca_partition(&data, 2);
+
+        assert_eq!(centroids.len(), 2);
+        let mut reds: Vec<f32> = centroids.iter().map(|c| c[0]).collect();
+        reds.sort_by(f32::total_cmp);
+        assert!((reds[0] - 41.5).abs() < 1e-4);
+        assert!((reds[1] - 201.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_variance_partition_stops_at_distinct_colors() {
+        let data: Vec<Vec3> = vec![[0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [255.0, 0.0, 0.0]];
+        assert_eq!(variance_partition(&data, 4).len(), 2);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::distance::euclidean_distance_squared;
+use crate::kmeans::types::{KMeansError, KMeansResult};
+use crate::kmeans::utils::{color_key, find_closest_centroid};
+use crate::types::VectorExt;
+use std::collections::HashMap;
+
+// Above this many distinct colors the pair search gets too slow, so PNN returns an error
+pub const MAX_PNN_COLORS: usize = 4096;
+
+// Counts and costs are kept in f64, since weights can be any positive f32 and the Ward
+// cost multiplies two of them
+struct Cluster<T> {
+    centroid: T,
+    count: f64,
+    // Indices of the distinct colors merged into this cluster
+    members: Vec<usize>,
+    // Cheapest merge partner and the cost of merging with it
+    nearest: usize,
+    cost: f64,
+}
+
+// Increase in total squared error when merging two clusters (Ward's criterion)
+#[inline]
+fn merge_cost<T: VectorExt>(a: &Cluster<T>, b: &Cluster<T>) -> f64 {
+    let scale = a.count * b.count / (a.count + b.count);
+    euclidean_distance_squared(&a.centroid, &b.centroid).0 as f64 * scale
+}
+
+// The cheapest partner for cluster i. Returns i itself when there is none.
+fn find_nearest<T: VectorExt>(clusters: &[Option<Cluster<T>>], i: usize) -> (usize, f64) {
+    let cluster = clusters[i].as_ref().unwrap();
+    let mut nearest = i;
+    let mut cost = f64::INFINITY;
+    for (j, other) in clusters.iter().enumerate() {
+        if let Some(other) = other {
+            if j != i {
+                let candidate = merge_cost(cluster, other);
+                if candidate < cost {
+                    cost = candidate;
+                    nearest = j;
+                }
+            }
+        }
+    }
+    (nearest, cost)
+}
+
+// Pairwise nearest neighbor clustering (Equitz, 1989), using the nearest-neighbor
+// pointer speedup from Fränti et al. (2000).
+// Starts with one cluster per distinct color and repeatedly merges the pair with the
+// smallest Ward cost until k clusters remain. The work grows with the square of the
+// number of distinct colors, so this suits pixel art and other low-color images, and
+// more than MAX_PNN_COLORS distinct colors is an error.
+pub fn quantize_pnn<T: VectorExt>(data: &[T], config: &KMeansConfig) -> KMeansResult<T> {
+    pnn(data, None, config)
+}
+
+// PNN on points that each count as `weight` copies of themselves, such as the distinct
+// colors of an image with their counts. Points with zero weight don't move the
+// centroids, and are assigned to the closest one.
+pub fn quantize_pnn_weighted<T: VectorExt>(
+    data: &[T],
+    weights: &[f32],
+    config: &KMeansConfig,
+) -> KMeansResult<T> {
+    pnn(data, Some(weights), config)
+}
+
+fn pnn<T: VectorExt>(
+    data: &[T],
+    weights: Option<&[f32]>,
+    config: &KMeansConfig,
+) -> KMeansResult<T> {
+    // Exact histogram of the distinct colors
+    let mut color_indices: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut pixel_colors = Vec::with_capacity(data.len());
+    let mut clusters: Vec<Option<Cluster<T>>> = Vec::new();
+    for (i, pixel) in data.iter().enumerate() {
+        let weight = weights.map_or(1.0, |weights| weights[i]);
+        if weight <= 0.0 {
+            pixel_colors.push(None);
+            continue;
+        }
+
+        let color = *color_indices.entry(color_key(pixel)).or_insert_with(|| {
+            clusters.push(Some(Cluster {
+                centroid: T::zero(),
+                count: 0.0,
+                members: vec![clusters.len()],
+                nearest: 0,
+                cost: f64::INFINITY,
+            }));
+            clusters.len() - 1
+        });
+        if clusters.len() > MAX_PNN_COLORS {
+            return Err(KMeansError(format!(
+                "PNN supports at most {} distinct colors",
+                MAX_PNN_COLORS
+            )));
+        }
+
+        // Running mean, so that any extra channels are averaged too
+        let cluster = clusters[color].as_mut().unwrap();
+        cluster.count += weight as f64;
+        cluster.centroid = cluster.centroid.add(
+            &pixel
+                .sub(&cluster.centroid)
+                .mul_scalar((weight as f64 / cluster.count) as f32),
+        );
+        pixel_colors.push(Some(color));
+    }
+    if clusters.is_empty() && !data.is_empty() {
+        return Err(KMeansError(
+            "PNN needs at least one point with a positive weight".to_string(),
+        ));
+    }
+
+    let mut remaining = clusters.len();
+    if remaining > config.k {
+        for i in 0..clusters.len() {
+            let (nearest, cost) = find_nearest(&clusters, i);
+            let cluster = clusters[i].as_mut().unwrap();
+            cluster.nearest = nearest;
+            cluster.cost = cost;
+        }
+    }
+
+    while remaining > config.k.max(1) {
+        let (a, _) = clusters
+            .iter()
+            .enumerate()
+            .filter_map(|(i, cluster)| cluster.as_ref().map(|cluster| (i, cluster.cost)))
+            .min_by(|x, y| x.1.total_cmp(&y.1))
+            .unwrap();
+        let b = clusters[a].as_ref().unwrap().nearest;
+        if b == a {
+            return Err(KMeansError(
+                "PNN found no cluster to merge, the weights may be too large".to_string(),
+            ));
+        }
+        let (a, b) = (a.min(b), a.max(b));
+
+        // Merge b into a
+        let merged = clusters[b].take().unwrap();
+        let cluster = clusters[a].as_mut().unwrap();
+        let count = cluster.count + merged.count;
+        cluster.centroid = cluster.centroid.add(
+            &merged
+                .centroid
+                .sub(&cluster.centroid)
+                .mul_scalar((merged.count / count) as f32),
+        );
+        cluster.count = count;
+        cluster.members.extend(merged.members);
+        remaining -= 1;
+
+        // Ward's distance satisfies the reducibility property, so merging can only
+        // invalidate the pointers of clusters that pointed at a or b.
+        if remaining > 1 {
+            for i in 0..clusters.len() {
+                let stale = match &clusters[i] {
+                    Some(cluster) => i == a || cluster.nearest == a || cluster.nearest == b,
+                    None => false,
+                };
+                if stale {
+                    let (nearest, cost) = find_nearest(&clusters, i);
+                    let cluster = clusters[i].as_mut().unwrap();
+                    cluster.nearest = nearest;
+                    cluster.cost = cost;
+                }
+            }
+        }
+    }
+
+    let mut color_clusters = vec![0; clusters.len()];
+    let mut centroids = Vec::with_capacity(remaining);
+    for cluster in clusters.iter().flatten() {
+        for &color in &cluster.members {
+            color_clusters[color] = centroids.len();
+        }
+        centroids.push(cluster.centroid);
+    }
+
+    let assignments = data
+        .iter()
+        .zip(pixel_colors.iter())
+        .map(|(pixel, color)| match color {
+            Some(color) => color_clusters[*color],
+            None => find_closest_centroid(pixel, &centroids),
+        })
+        .collect();
+    Ok((assignments, centroids))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec3;
+
+    #[test]
+    fn test_pnn_merges_closest_colors_first() {
+        let data: Vec<Vec3> = vec![
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [4.0, 0.0, 0.0],
+            [100.0, 100.0, 100.0],
+            [104.0, 100.0, 100.0],
+            [250.0, 250.0, 250.0],
+        ];
+        let config = KMeansConfig {
+            k: 3,
+            ..Default::default()
+        };
+
+        let (assignments, centroids) = quantize_pnn(&data, &config).unwrap();
+
+        assert_eq!(assignments, vec![0, 0, 0, 1, 1, 2]);
+        assert_eq!(
+            centroids,
+            vec![
+                [4.0 / 3.0, 0.0, 0.0],
+                [102.0, 100.0, 100.0],
+                [250.0, 250.0, 250.0]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_weights_count_as_repeated_colors() {
+        let data: Vec<Vec3> = vec![
+            [0.0, 0.0, 0.0],
+            [4.0, 0.0, 0.0],
+            [100.0, 100.0, 100.0],
+            [104.0, 100.0, 100.0],
+            [250.0, 250.0, 250.0],
+            [-0.0, 0.0, 9.0],
+        ];
+        let weights = [2.0, 1.0, 1.0, 1.0, 1.0, 0.0];
+        let config = KMeansConfig {
+            k: 3,
+            ..Default::default()
+        };
+
+        let (assignments, centroids) = quantize_pnn_weighted(&data, &weights, &config).unwrap();
+
+        assert_eq!(assignments, vec![0, 0, 1, 1, 2, 0]);
+        assert_eq!(centroids[0], [4.0 / 3.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_uniform_weights_give_the_same_palette_at_any_scale() {
+        let data: Vec<Vec3> = (0..200)
+            .map(|i| {
+                [
+                    (i * 37 % 256) as f32,
+                    (i * 91 % 256) as f32,
+                    (i * 13 % 256) as f32,
+                ]
+            })
+            .collect();
+        let config = KMeansConfig {
+            k: 8,
+            ..Default::default()
+        };
+        let unweighted = quantize_pnn(&data, &config).unwrap();
+
+        for scale in [1e-30, 1.0, 1e30, f32::MAX] {
+            let weights = vec![scale; data.len()];
+            let (assignments, centroids) = quantize_pnn_weighted(&data, &weights, &config).unwrap();
+            assert_eq!(assignments, unweighted.0, "{}", scale);
+            for (centroid, expected) in centroids.iter().zip(unweighted.1.iter()) {
+                for channel in 0..3 {
+                    assert!(
+                        (centroid[channel] - expected[channel]).abs() < 1e-3,
+                        "{}",
+                        scale
+                    );
+                }
+            }
+        }
+
+        // Weights far apart don't overflow the merge costs
+        let weights: Vec<f32> = (0..data.len())
+            .map(|i| if i % 2 == 0 { 1e30 } else { 1.0 })
+            .collect();
+        let (_, centroids) = quantize_pnn_weighted(&data, &weights, &config).unwrap();
+        assert_eq!(centroids.len(), 8);
+    }
+
+    #[test]
+    fn test_too_many_colors_is_an_error() {
+        let data: Vec<Vec3> = (0..=MAX_PNN_COLORS)
+            .map(|i| [(i % 256) as f32, (i / 256) as f32, 0.0])
+            .collect();
+        let config = KMeansConfig {
+            k: 16,
+            ..Default::default()
+        };
+
+        assert!(quantize_pnn(&data, &config).is_err());
+    }
+}
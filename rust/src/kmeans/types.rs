@@ -3,6 +3,8 @@ pub type Centroids<T> = Vec<T>;
 pub type CentroidSums<T> = Vec<T>;
 pub type Assignments = Vec<usize>;
 pub type CentroidCounts = Vec<usize>;
+// Total weight of the points in each cluster
+pub type CentroidWeights = Vec<f32>;
 
 // Result
 pub type KMeansResult<T> = Result<(Assignments, Centroids<T>), KMeansError>;
@@ -1,6 +1,22 @@
+use crate::kmeans::config::{EmptyClusterPolicy, KMeansConfig};
 use crate::kmeans::distance::euclidean_distance_squared;
-use crate::kmeans::distance::SquaredEuclideanDistance;
+use crate::kmeans::distance::{EuclideanDistance, SquaredEuclideanDistance};
+use crate::kmeans::initializer::fill_farthest_first;
+use crate::kmeans::types::{Assignments, CentroidCounts, CentroidSums, CentroidWeights, Centroids};
 use crate::types::VectorExt;
+use std::collections::HashSet;
+
+// Exact key of a color's RGB channels, from their bit patterns. Adding 0 turns -0.0 into
+// 0.0, so that both are the same color.
+pub fn color_key<T: VectorExt>(color: &T) -> [u32; 3] {
+    [0, 1, 2].map(|channel| (color[channel] + 0.0).to_bits())
+}
+
+// Exact number of distinct colors, ignoring any channels past RGB. Colors are compared
+// as floats, not rounded to bytes, since k-means input needn't be in byte units.
+pub fn num_distinct_colors<T: VectorExt>(data: &[T]) -> usize {
+    data.iter().map(color_key).collect::<HashSet<_>>().len()
+}
 
 // Return the index of closest centroid and distance to that centroid
 pub fn find_closest_centroid<T: VectorExt>(pixel: &T, centroids: &[T]) -> usize {
@@ -29,6 +45,410 @@ pub fn has_converged<T: VectorExt>(
         .all(|(a, b)| euclidean_distance_squared(a, b) < tolerance)
 }
 
+// Seed for one of several restarts. The first restart keeps the seed as is, the others
+// offset it, so that seeded runs stay reproducible. Unseeded runs stay unseeded.
+pub fn restart_seed(seed: Option<u64>, restart: usize) -> Option<u64> {
+    seed.map(|seed| seed.wrapping_add(restart as u64))
+}
+
+// Config for a 2-means run that splits one cluster, the `split`th of the run. The seed is
+// offset per split so that repeated splits don't share a random sequence.
+pub fn split_config(config: &KMeansConfig, split: usize) -> KMeansConfig {
+    KMeansConfig {
+        k: 2,
+        initializer: config.initializer.for_split(),
+        seed: restart_seed(config.seed, split),
+        ..config.clone()
+    }
+}
+
+// Sum of squared distances from every point to its assigned centroid
+pub fn inertia<T: VectorExt>(
+    data: &[T],
+    assignments: &[usize],
+    centroids: &[T],
+) -> SquaredEuclideanDistance {
+    data.iter()
+        .zip(assignments.iter())
+        .map(|(pixel, &cluster)| euclidean_distance_squared(pixel, &centroids[cluster]))
+        .sum()
+}
+
+// Inertia of points that each count as `weight` copies of themselves
+pub fn weighted_inertia<T: VectorExt>(
+    data: &[T],
+    weights: &[f32],
+    assignments: &[usize],
+    centroids: &[T],
+) -> SquaredEuclideanDistance {
+    data.iter()
+        .zip(weights.iter().zip(assignments.iter()))
+        .map(|(pixel, (&weight, &cluster))| {
+            SquaredEuclideanDistance(
+                weight * euclidean_distance_squared(pixel, &centroids[cluster]).0,
+            )
+        })
+        .sum()
+}
+
+// Bayesian information criterion of a clustering, treating the clusters as spherical
+// gaussians with a shared variance (Pelleg & Moore, 2000). Higher is better.
+pub fn bic<T: VectorExt>(data: &[T], assignments: &[usize], centroids: &[T]) -> f64 {
+    // Distances only look at the color channels
+    const DIMENSIONS: f64 = 3.0;
+
+    let n = data.len() as f64;
+    let k = centroids.len() as f64;
+    if data.is_empty() || centroids.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+
+    let mut counts = vec![0usize; centroids.len()];
+    for &cluster in assignments {
+        counts[cluster] += 1;
+    }
+
+    let sse = inertia(data, assignments, centroids).0 as f64;
+    // Floor the variance so that an exact fit doesn't produce an infinite likelihood
+    let variance = (sse / (DIMENSIONS * (n - k).max(1.0))).max(1e-6);
+
+    let log_likelihood = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let count = count as f64;
+            count * (count / n).ln()
+        })
+        .sum::<f64>()
+        - n * DIMENSIONS / 2.0 * (2.0 * std::f64::consts::PI * variance).ln()
+        - DIMENSIONS * (n - k).max(0.0) / 2.0;
+
+    // k - 1 mixing weights, k means and the shared variance
+    let parameters = k * (DIMENSIONS + 1.0);
+    log_likelihood - parameters / 2.0 * n.ln()
+}
+
+// Replaces every centroid with the closest point assigned to it, so that centroids
+// are colors that actually occur. Returns how far each centroid moved.
+// Centroids with no points assigned stay where they are.
+pub fn snap_to_members<T: VectorExt>(
+    data: &[T],
+    assignments: &[usize],
+    centroids: &mut [T],
+) -> Vec<EuclideanDistance> {
+    let mut closest: Vec<Option<(SquaredEuclideanDistance, T)>> = vec![None; centroids.len()];
+    for (pixel, &cluster) in data.iter().zip(assignments.iter()) {
+        let distance = euclidean_distance_squared(pixel, &centroids[cluster]);
+        if closest[cluster].is_none_or(|(best, _)| distance < best) {
+            closest[cluster] = Some((distance, *pixel));
+        }
+    }
+
+    centroids
+        .iter_mut()
+        .zip(closest)
+        .map(|(centroid, closest)| match closest {
+            Some((distance, pixel)) => {
+                *centroid = pixel;
+                distance.sqrt()
+            }
+            None => EuclideanDistance(0.0),
+        })
+        .collect()
+}
+
+pub fn cluster_sums<T: VectorExt>(
+    data: &[T],
+    assignments: &[usize],
+    k: usize,
+) -> (CentroidSums<T>, CentroidCounts) {
+    let mut sums = vec![T::zero(); k];
+    let mut counts = vec![0; k];
+    for (pixel, &cluster) in data.iter().zip(assignments.iter()) {
+        sums[cluster] = sums[cluster].add(pixel);
+        counts[cluster] += 1;
+    }
+    (sums, counts)
+}
+
+// Like cluster_sums, for points that each count as `weight` copies of themselves.
+// Also returns the total weight of each cluster. Without weights, every point counts once.
+pub fn weighted_cluster_sums<T: VectorExt>(
+    data: &[T],
+    weights: Option<&[f32]>,
+    assignments: &[usize],
+    k: usize,
+) -> (CentroidSums<T>, CentroidWeights, CentroidCounts) {
+    let mut sums = vec![T::zero(); k];
+    let mut totals = vec![0.0; k];
+    let mut counts = vec![0; k];
+    for (i, (pixel, &cluster)) in data.iter().zip(assignments.iter()).enumerate() {
+        let weight = weights.map_or(1.0, |weights| weights[i]);
+        sums[cluster] = sums[cluster].add(&pixel.mul_scalar(weight));
+        totals[cluster] += weight;
+        counts[cluster] += 1;
+    }
+    (sums, totals, counts)
+}
+
+// Moves each centroid into new_centroids, to the mean of its cluster given the cluster's
+// sum and total weight, and records how far it moved. The distances are true distances,
+// not squared, because the triangle inequality bounds rely on them.
+pub fn move_centroids<T: VectorExt>(
+    centroids: &[T],
+    new_centroids: &mut [T],
+    centroid_sums: &[T],
+    cluster_weight: impl Fn(usize) -> f32,
+    centroid_move_distances: &mut [EuclideanDistance],
+) {
+    for (j, (current_centroid, new_centroid)) in
+        centroids.iter().zip(new_centroids.iter_mut()).enumerate()
+    {
+        let weight = cluster_weight(j);
+        if weight <= 0.0 {
+            // Empty under Keep/Drop, or when repair found nothing to move. Clusters of
+            // zero-weight points have no mean either.
+            *new_centroid = *current_centroid;
+            centroid_move_distances[j] = EuclideanDistance(0.0);
+            continue;
+        }
+
+        *new_centroid = centroid_sums[j].div_scalar(weight);
+        centroid_move_distances[j] =
+            euclidean_distance_squared(current_centroid, new_centroid).sqrt();
+    }
+}
+
+// Octree, Wu and NeuQuant bin colors like bytes, so colors within a bin of each other,
+// such as the dark end of a color space fitted into 0-255, share a palette color. This
+// drops palette colors that no pixel maps to and tops the palette up to k farthest-first,
+// as the box-splitting initializers do. Palettes that are already full are left as is.
+pub fn fill_binned_palette<T: VectorExt>(
+    data: &[T],
+    (assignments, centroids): (Assignments, Centroids<T>),
+    k: usize,
+) -> (Assignments, Centroids<T>) {
+    let mut used = vec![false; centroids.len()];
+    assignments.iter().for_each(|&cluster| used[cluster] = true);
+    if centroids.len() >= k && !used.contains(&false) {
+        return (assignments, centroids);
+    }
+
+    let used_centroids = centroids
+        .into_iter()
+        .zip(used)
+        .filter_map(|(centroid, used)| used.then_some(centroid))
+        .collect();
+    let centroids = fill_farthest_first(data, used_centroids, k);
+    let assignments = data
+        .iter()
+        .map(|pixel| find_closest_centroid(pixel, &centroids))
+        .collect();
+    (assignments, centroids)
+}
+
+// Mean of every cluster. Clusters with no points keep their centroid.
+pub fn cluster_means<T: VectorExt>(
+    data: &[T],
+    assignments: &[usize],
+    centroids: &[T],
+) -> Centroids<T> {
+    let (sums, counts) = cluster_sums(data, assignments, centroids.len());
+    centroids
+        .iter()
+        .zip(sums.iter().zip(counts.iter()))
+        .map(|(centroid, (sum, &count))| match count {
+            0 => *centroid,
+            count => sum.div_scalar(count as f32),
+        })
+        .collect()
+}
+
+// Reassigns pixels to empty clusters as the policy says, so that the next centroid
+// update moves them. Returns the pixels that changed cluster. Dropped clusters are
+// left alone until the end of the run, see drop_empty_clusters.
+pub fn fill_empty_clusters<T: VectorExt>(
+    data: &[T],
+    assignments: &mut [usize],
+    centroids: &[T],
+    policy: EmptyClusterPolicy,
+) -> Vec<usize> {
+    let k = centroids.len();
+    let mut counts = vec![0usize; k];
+    for &cluster in assignments.iter() {
+        counts[cluster] += 1;
+    }
+
+    let mut moved = Vec::new();
+    let empty: Vec<usize> = (0..k).filter(|&cluster| counts[cluster] == 0).collect();
+    if empty.is_empty() {
+        return moved;
+    }
+
+    match policy {
+        EmptyClusterPolicy::ReseedFarthest => {
+            let mut distances: Vec<f32> = data
+                .iter()
+                .zip(assignments.iter())
+                .map(|(pixel, &cluster)| euclidean_distance_squared(pixel, &centroids[cluster]).0)
+                .collect();
+            for cluster in empty {
+                // Taking the only pixel of another cluster would just empty that one
+                let Some(farthest) = (0..data.len())
+                    .filter(|&i| counts[assignments[i]] > 1 && distances[i] > 0.0)
+                    .max_by(|&a, &b| distances[a].total_cmp(&distances[b]))
+                else {
+                    break; // every remaining pixel sits on a centroid
+                };
+
+                counts[assignments[farthest]] -= 1;
+                counts[cluster] += 1;
+                assignments[farthest] = cluster;
+                moved.push(farthest);
+
+                // Pixels of the same color are no longer far from anything
+                let seed = data[farthest];
+                for (distance, pixel) in distances.iter_mut().zip(data) {
+                    *distance = distance.min(euclidean_distance_squared(pixel, &seed).0);
+                }
+            }
+        }
+        EmptyClusterPolicy::SplitLargest => {
+            let mut unsplittable = vec![false; k];
+            for cluster in empty {
+                while let Some(largest) = (0..k)
+                    .filter(|&c| !unsplittable[c] && counts[c] > 1)
+                    .max_by_key(|&c| counts[c])
+                {
+                    let members: Vec<usize> = (0..data.len())
+                        .filter(|&i| assignments[i] == largest)
+                        .collect();
+                    let (channel, mean) = widest_channel(data, &members);
+                    let half: Vec<usize> = members
+                        .into_iter()
+                        .filter(|&i| data[i][channel] > mean)
+                        .collect();
+                    if half.is_empty() {
+                        unsplittable[largest] = true; // a single color
+                        continue;
+                    }
+
+                    counts[largest] -= half.len();
+                    counts[cluster] += half.len();
+                    for &i in &half {
+                        assignments[i] = cluster;
+                    }
+                    moved.extend(half);
+                    break;
+                }
+            }
+        }
+        EmptyClusterPolicy::Keep | EmptyClusterPolicy::Drop => {}
+    }
+    moved
+}
+
+// The color channel the points spread across the most, and their mean along it
+fn widest_channel<T: VectorExt>(data: &[T], indices: &[usize]) -> (usize, f32) {
+    let n = indices.len() as f64;
+    (0..3)
+        .map(|channel| {
+            let mean = indices
+                .iter()
+                .map(|&i| data[i][channel] as f64)
+                .sum::<f64>()
+                / n;
+            let variance = indices
+                .iter()
+                .map(|&i| (data[i][channel] as f64 - mean).powi(2))
+                .sum::<f64>();
+            (channel, mean, variance)
+        })
+        .max_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(channel, mean, _)| (channel, mean as f32))
+        .unwrap()
+}
+
+// Removes clusters with no points, renumbering the assignments to match
+pub fn drop_empty_clusters<T: VectorExt>(
+    assignments: Assignments,
+    centroids: Centroids<T>,
+) -> (Assignments, Centroids<T>) {
+    let mut counts = vec![0usize; centroids.len()];
+    for &cluster in &assignments {
+        counts[cluster] += 1;
+    }
+    if counts.iter().all(|&count| count > 0) {
+        return (assignments, centroids);
+    }
+
+    let mut remap = vec![0; centroids.len()];
+    let mut kept = Vec::with_capacity(centroids.len());
+    for (cluster, centroid) in centroids.into_iter().enumerate() {
+        if counts[cluster] > 0 {
+            remap[cluster] = kept.len();
+            kept.push(centroid);
+        }
+    }
+    let assignments = assignments
+        .into_iter()
+        .map(|cluster| remap[cluster])
+        .collect();
+    (assignments, kept)
+}
+
+// Bound-based algorithms keep an upper bound and one or more lower bounds per pixel,
+// which only hold for the pixel's own cluster. Pixels that empty-cluster repair moved
+// get bounds that force a full distance check on the next pass.
+pub fn reset_moved_bounds(
+    moved: &[usize],
+    upper_bounds: &mut [EuclideanDistance],
+    lower_bounds: &mut [EuclideanDistance],
+) {
+    let per_pixel = lower_bounds.len() / upper_bounds.len().max(1);
+    for &i in moved {
+        upper_bounds[i] = EuclideanDistance(f32::MAX);
+        lower_bounds[i * per_pixel..(i + 1) * per_pixel].fill(EuclideanDistance(0.0));
+    }
+}
+
+// Applies the end of run part of the policy
+pub fn finish_empty_clusters<T: VectorExt>(
+    assignments: Assignments,
+    centroids: Centroids<T>,
+    policy: EmptyClusterPolicy,
+) -> (Assignments, Centroids<T>) {
+    match policy {
+        EmptyClusterPolicy::Drop => drop_empty_clusters(assignments, centroids),
+        _ => (assignments, centroids),
+    }
+}
+
+// Gaussian blobs around the centers, which take turns getting a point. Test data for
+// the algorithms that look for the number of clusters.
+#[cfg(test)]
+pub fn gaussian_blobs(
+    centers: &[crate::types::Vec3],
+    n: usize,
+    spread: f32,
+    seed: u64,
+) -> Vec<crate::types::Vec3> {
+    use rand::Rng;
+
+    let mut rng = crate::kmeans::initializer::get_seedable_rng(Some(seed));
+    (0..n)
+        .map(|i| {
+            // Box-Muller transform
+            centers[i % centers.len()].map(|channel| {
+                let u: f32 = rng.gen_range(f32::EPSILON..1.0);
+                let v: f32 = rng.gen();
+                let normal = (-2.0 * u.ln()).sqrt() * (std::f32::consts::TAU * v).cos();
+                channel + spread * normal
+            })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -46,4 +466,38 @@ mod tests {
         let closest_index = find_closest_centroid(&pixel, &centroids);
         assert_eq!(closest_index, 1);
     }
+
+    #[test]
+    fn test_distinct_colors_are_exact() {
+        let colors = vec![
+            [0.0, 10.0, 20.0],
+            [-0.0, 10.0, 20.0],
+            [0.0, 10.2, 19.8],
+            [0.0, 10.0, 20.0],
+        ];
+
+        assert_eq!(num_distinct_colors(&colors), 2);
+    }
+
+    #[test]
+    fn test_snap_to_members() {
+        let data = vec![[0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [200.0, 0.0, 0.0]];
+        let assignments = vec![0, 0, 1];
+        let mut centroids = vec![[4.0, 0.0, 0.0], [200.0, 0.0, 0.0], [50.0, 50.0, 50.0]];
+
+        let distances = snap_to_members(&data, &assignments, &mut centroids);
+
+        assert_eq!(
+            centroids,
+            vec![[0.0, 0.0, 0.0], [200.0, 0.0, 0.0], [50.0, 50.0, 50.0]]
+        );
+        assert_eq!(
+            distances,
+            vec![
+                EuclideanDistance(4.0),
+                EuclideanDistance(0.0),
+                EuclideanDistance(0.0)
+            ]
+        );
+    }
 }
@@ -0,0 +1,385 @@
+use crate::kmeans::config::KMeansConfig;
+use crate::kmeans::types::{Assignments, Centroids};
+use crate::types::VectorExt;
+
+// Xiaolin Wu's greedy orthogonal bipartition (Graphics Gems II, 1991).
+// Colors are binned into a 32x32x32 histogram (5 bits per channel). The cumulative
+// moments of that histogram let us compute the count, sum and sum of squares of any
+// box in constant time, so each split can pick the cut that minimizes the variance.
+
+const HISTOGRAM_BITS: u32 = 5;
+// One extra slot per axis so that index 0 is an all-zero border for the cumulative sums
+const SIDE: usize = (1 << HISTOGRAM_BITS) + 1;
+const HISTOGRAM_SIZE: usize = SIDE * SIDE * SIDE;
+
+const RED: usize = 0;
+const GREEN: usize = 1;
+const BLUE: usize = 2;
+
+#[inline]
+fn index(r: usize, g: usize, b: usize) -> usize {
+    r * SIDE * SIDE + g * SIDE + b
+}
+
+#[inline]
+fn bin(value: f32) -> usize {
+    ((value.clamp(0.0, 255.0) as u32) >> (8 - HISTOGRAM_BITS)) as usize + 1
+}
+
+struct Moments {
+    weight: Vec<f64>,
+    // Per-channel sums, indexed by RED, GREEN, BLUE
+    sums: [Vec<f64>; 3],
+    squares: Vec<f64>,
+}
+
+// Boxes span the half-open range (lower, upper] on each axis
+#[derive(Debug, Clone, Copy, Default)]
+struct ColorBox {
+    lower: [usize; 3],
+    upper: [usize; 3],
+}
+
+impl ColorBox {
+    fn volume(&self) -> usize {
+        (0..3)
+            .map(|axis| self.upper[axis] - self.lower[axis])
+            .product()
+    }
+}
+
+impl Moments {
+    fn from_data<T: VectorExt>(data: &[T], weights: &[f32]) -> Self {
+        let mut moments = Moments {
+            weight: vec![0.0; HISTOGRAM_SIZE],
+            sums: [
+                vec![0.0; HISTOGRAM_SIZE],
+                vec![0.0; HISTOGRAM_SIZE],
+                vec![0.0; HISTOGRAM_SIZE],
+            ],
+            squares: vec![0.0; HISTOGRAM_SIZE],
+        };
+
+        for (pixel, &weight) in data.iter().zip(weights.iter()) {
+            let cell = index(bin(pixel[RED]), bin(pixel[GREEN]), bin(pixel[BLUE]));
+            let (r, g, b) = (pixel[RED] as f64, pixel[GREEN] as f64, pixel[BLUE] as f64);
+            let weight = weight as f64;
+            moments.weight[cell] += weight;
+            moments.sums[RED][cell] += weight * r;
+            moments.sums[GREEN][cell] += weight * g;
+            moments.sums[BLUE][cell] += weight * b;
+            moments.squares[cell] += weight * (r * r + g * g + b * b);
+        }
+
+        moments.accumulate();
+        moments
+    }
+
+    // Convert the histogram into cumulative moments, so that entry (r, g, b) holds
+    // the total over the box (0, r] x (0, g] x (0, b].
+    fn accumulate(&mut self) {
+        let Moments {
+            weight,
+            sums,
+            squares,
+        } = self;
+        let [sums_r, sums_g, sums_b] = sums;
+        for moment in [weight, sums_r, sums_g, sums_b, squares] {
+            for r in 1..SIDE {
+                let mut area = [0.0; SIDE];
+                for g in 1..SIDE {
+                    let mut line = 0.0;
+                    for b in 1..SIDE {
+                        line += moment[index(r, g, b)];
+                        area[b] += line;
+                        moment[index(r, g, b)] = moment[index(r - 1, g, b)] + area[b];
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Sum of a cumulative moment over a box, by inclusion-exclusion on its corners
+fn volume(color_box: &ColorBox, moment: &[f64]) -> f64 {
+    let mut total = 0.0;
+    for corner in 0..8 {
+        let mut position = [0; 3];
+        let mut sign = 1.0;
+        for (axis, coordinate) in position.iter_mut().enumerate() {
+            if corner & (1 << axis) == 0 {
+                *coordinate = color_box.upper[axis];
+            } else {
+                *coordinate = color_box.lower[axis];
+                sign = -sign;
+            }
+        }
+        total += sign * moment[index(position[RED], position[GREEN], position[BLUE])];
+    }
+    total
+}
+
+// The part of `volume` that depends on the position of the box's upper bound on `axis`.
+// The sum over (lower, position] on `axis` is bottom(..) + top(.., position).
+fn top(color_box: &ColorBox, axis: usize, position: usize, moment: &[f64]) -> f64 {
+    let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut total = 0.0;
+    for corner in 0..4 {
+        let mut point = [0; 3];
+        let mut sign = 1.0;
+        point[axis] = position;
+        for (bit, other_axis) in [a, b].into_iter().enumerate() {
+            if corner & (1 << bit) == 0 {
+                point[other_axis] = color_box.upper[other_axis];
+            } else {
+                point[other_axis] = color_box.lower[other_axis];
+                sign = -sign;
+            }
+        }
+        total += sign * moment[index(point[RED], point[GREEN], point[BLUE])];
+    }
+    total
+}
+
+fn bottom(color_box: &ColorBox, axis: usize, moment: &[f64]) -> f64 {
+    -top(color_box, axis, color_box.lower[axis], moment)
+}
+
+fn variance(color_box: &ColorBox, moments: &Moments) -> f64 {
+    let weight = volume(color_box, &moments.weight);
+    if weight == 0.0 {
+        return 0.0;
+    }
+    let squared_sum: f64 = moments
+        .sums
+        .iter()
+        .map(|sum| volume(color_box, sum).powi(2))
+        .sum();
+    volume(color_box, &moments.squares) - squared_sum / weight
+}
+
+// Find the cut along `axis` that maximizes the between-box sum of squares, which is
+// the same as minimizing the sum of the two halves' variances.
+fn maximize(
+    color_box: &ColorBox,
+    axis: usize,
+    moments: &Moments,
+    whole_weight: f64,
+    whole_sums: [f64; 3],
+) -> Option<(usize, f64)> {
+    let base_weight = bottom(color_box, axis, &moments.weight);
+    let base_sums = [
+        bottom(color_box, axis, &moments.sums[RED]),
+        bottom(color_box, axis, &moments.sums[GREEN]),
+        bottom(color_box, axis, &moments.sums[BLUE]),
+    ];
+
+    let mut best: Option<(usize, f64)> = None;
+    for position in (color_box.lower[axis] + 1)..color_box.upper[axis] {
+        let half_weight = base_weight + top(color_box, axis, position, &moments.weight);
+        if half_weight == 0.0 || half_weight == whole_weight {
+            continue; // one of the halves would be empty
+        }
+
+        let mut score = 0.0;
+        let mut other_score = 0.0;
+        for channel in 0..3 {
+            let half_sum =
+                base_sums[channel] + top(color_box, axis, position, &moments.sums[channel]);
+            score += half_sum * half_sum;
+            let other_half_sum = whole_sums[channel] - half_sum;
+            other_score += other_half_sum * other_half_sum;
+        }
+        let score = score / half_weight + other_score / (whole_weight - half_weight);
+
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((position, score));
+        }
+    }
+    best
+}
+
+fn cut(color_box: &mut ColorBox, moments: &Moments) -> Option<ColorBox> {
+    let whole_weight = volume(color_box, &moments.weight);
+    let whole_sums = [
+        volume(color_box, &moments.sums[RED]),
+        volume(color_box, &moments.sums[GREEN]),
+        volume(color_box, &moments.sums[BLUE]),
+    ];
+
+    let (axis, (position, _)) = [RED, GREEN, BLUE]
+        .into_iter()
+        .filter_map(|axis| {
+            maximize(color_box, axis, moments, whole_weight, whole_sums).map(|cut| (axis, cut))
+        })
+        .fold(
+            None,
+            |best: Option<(usize, (usize, f64))>, candidate| match best {
+                Some(best) if best.1 .1 >= candidate.1 .1 => Some(best),
+                _ => Some(candidate),
+            },
+        )?;
+
+    let mut other = *color_box;
+    color_box.upper[axis] = position;
+    other.lower[axis] = position;
+    Some(other)
+}
+
+// Returns the boxes of the partition along with the box index for each histogram cell
+fn partition(moments: &Moments, k: usize) -> (Vec<ColorBox>, Vec<usize>) {
+    if k == 0 {
+        return (Vec::new(), vec![0; HISTOGRAM_SIZE]);
+    }
+
+    let mut boxes = vec![ColorBox {
+        lower: [0; 3],
+        upper: [SIDE - 1; 3],
+    }];
+    let mut variances = vec![0.0];
+
+    let mut next = 0;
+    while boxes.len() < k {
+        match cut(&mut boxes[next], moments) {
+            Some(other) => {
+                let splittable_variance = |color_box: &ColorBox| {
+                    if color_box.volume() > 1 {
+                        variance(color_box, moments)
+                    } else {
+                        0.0
+                    }
+                };
+                variances[next] = splittable_variance(&boxes[next]);
+                variances.push(splittable_variance(&other));
+                boxes.push(other);
+            }
+            // This box can't be split, so don't try it again
+            None => variances[next] = 0.0,
+        }
+
+        let (best, &best_variance) = variances
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+            .unwrap();
+        if best_variance <= 0.0 {
+            break;
+        }
+        next = best;
+    }
+
+    let mut tags = vec![0; HISTOGRAM_SIZE];
+    for (tag, color_box) in boxes.iter().enumerate() {
+        for r in (color_box.lower[RED] + 1)..=color_box.upper[RED] {
+            for g in (color_box.lower[GREEN] + 1)..=color_box.upper[GREEN] {
+                for b in (color_box.lower[BLUE] + 1)..=color_box.upper[BLUE] {
+                    tags[index(r, g, b)] = tag;
+                }
+            }
+        }
+    }
+
+    (boxes, tags)
+}
+
+pub fn wu<T: VectorExt>(data: &[T], k: usize) -> Centroids<T> {
+    wu_partition(data, &vec![1.0; data.len()], k).1
+}
+
+// Wu on points that each count as `weight` copies of themselves
+pub fn weighted_wu<T: VectorExt>(data: &[T], weights: &[f32], k: usize) -> Centroids<T> {
+    wu_partition(data, weights, k).1
+}
+
+pub fn quantize_wu<T: VectorExt>(data: &[T], config: &KMeansConfig) -> (Assignments, Centroids<T>) {
+    wu_partition(data, &vec![1.0; data.len()], config.k)
+}
+
+// Pixels that share a histogram cell always end up in the same box, so images whose
+// colors fall into fewer than k cells get a smaller palette.
+fn wu_partition<T: VectorExt>(
+    data: &[T],
+    weights: &[f32],
+    k: usize,
+) -> (Assignments, Centroids<T>) {
+    if data.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+
+    let moments = Moments::from_data(data, weights);
+    let (boxes, tags) = partition(&moments, k);
+
+    let mut assignments: Assignments = data
+        .iter()
+        .map(|pixel| tags[index(bin(pixel[RED]), bin(pixel[GREEN]), bin(pixel[BLUE]))])
+        .collect();
+
+    // Centroids are the means of the pixels in each box. This also averages any
+    // channels beyond RGB, which the histogram doesn't track.
+    let mut sums = vec![T::zero(); boxes.len()];
+    let mut totals = vec![0.0f32; boxes.len()];
+    for (pixel, (&tag, &weight)) in data.iter().zip(assignments.iter().zip(weights.iter())) {
+        sums[tag] = sums[tag].add(&pixel.mul_scalar(weight));
+        totals[tag] += weight;
+    }
+
+    // The cut never produces an empty box, but keep the indices dense regardless.
+    let mut remap = vec![usize::MAX; boxes.len()];
+    let mut centroids = Vec::with_capacity(boxes.len());
+    for (tag, (sum, &total)) in sums.iter().zip(totals.iter()).enumerate() {
+        if total > 0.0 {
+            remap[tag] = centroids.len();
+            centroids.push(sum.div_scalar(total));
+        }
+    }
+    assignments.iter_mut().for_each(|tag| *tag = remap[*tag]);
+
+    (assignments, centroids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec4;
+
+    #[test]
+    fn test_wu_separates_distinct_clusters() {
+        let data: Vec<Vec4> = vec![
+            [250.0, 5.0, 5.0, 255.0],
+            [255.0, 0.0, 0.0, 255.0],
+            [0.0, 0.0, 250.0, 255.0],
+            [5.0, 5.0, 255.0, 255.0],
+            [0.0, 255.0, 0.0, 255.0],
+            [5.0, 250.0, 5.0, 255.0],
+        ];
+        let config = KMeansConfig {
+            k: 3,
+            ..Default::default()
+        };
+
+        let (assignments, centroids) = quantize_wu(&data, &config);
+
+        assert_eq!(centroids.len(), 3);
+        assert_eq!(assignments[0], assignments[1]);
+        assert_eq!(assignments[2], assignments[3]);
+        assert_eq!(assignments[4], assignments[5]);
+        assert_ne!(assignments[0], assignments[2]);
+        assert_ne!(assignments[0], assignments[4]);
+        assert_ne!(assignments[2], assignments[4]);
+        assert_eq!(centroids[assignments[0]], [252.5, 2.5, 2.5, 255.0]);
+    }
+
+    #[test]
+    fn test_wu_returns_fewer_colors_than_requested_for_simple_images() {
+        let data: Vec<Vec4> = vec![[10.0, 20.0, 30.0, 255.0], [200.0, 100.0, 0.0, 255.0]];
+        let config = KMeansConfig {
+            k: 8,
+            ..Default::default()
+        };
+
+        let (assignments, centroids) = quantize_wu(&data, &config);
+
+        assert_eq!(centroids.len(), 2);
+        assert_ne!(assignments[0], assignments[1]);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::kmeans::config::{KMeansAlgorithm, KMeansConfig};
+use crate::kmeans::distance::{euclidean_distance_squared, EuclideanDistance};
+use crate::kmeans::initializer::Initializer;
+use crate::kmeans::lloyd::kmeans_lloyd;
+use crate::kmeans::types::{Assignments, CentroidCounts, CentroidSums, Centroids};
+use crate::kmeans::utils::{
+    cluster_sums, fill_empty_clusters, finish_empty_clusters, has_converged, move_centroids,
+    reset_moved_bounds,
+};
+use crate::types::VectorExt;
+use itertools::izip;
+
+type UpperBounds = Vec<EuclideanDistance>;
+// One lower bound per (pixel, group) pair, stored row-major by pixel.
+type GroupLowerBounds = Vec<EuclideanDistance>;
+
+// The original paper suggests k / 10 groups as a good trade-off between
+// filtering power and bookkeeping.
+const CENTROIDS_PER_GROUP: usize = 10;
+const GROUPING_ITERATIONS: usize = 5;
+
+struct Groups {
+    members: Vec<Vec<usize>>,
+    group_of: Vec<usize>,
+}
+
+pub fn kmeans_yinyang<T: VectorExt>(
+    data: &[T],
+    config: &KMeansConfig,
+) -> (Assignments, Centroids<T>) {
+    let mut centroids = config
+        .initializer
+        .initialize_centroids(data, config.k, config.seed);
+
+    let k = config.k;
+    assert!(data.len() >= k);
+
+    let groups = group_centroids(&centroids, config);
+    let num_groups = groups.members.len();
+
+    let (mut centroid_sums, mut centroid_counts, mut upper_bounds, mut lower_bounds, mut clusters) =
+        initialize_yinyang(data, &centroids, &groups);
+
+    let mut centroid_move_distances = vec![EuclideanDistance(0.0); k];
+    let mut group_move_distances = vec![EuclideanDistance(0.0); num_groups];
+    let mut new_centroids = centroids.clone();
+
+    // Scratch space for the closest and second closest centroid in each group
+    let mut group_best = vec![(EuclideanDistance(f32::MAX), 0); num_groups];
+    let mut group_second_best = vec![EuclideanDistance(f32::MAX); num_groups];
+    let mut group_visited = vec![false; num_groups];
+
+    for _ in 0..config.max_iterations {
+        if centroid_counts.contains(&0) {
+            let moved =
+                fill_empty_clusters(data, &mut clusters, &centroids, config.empty_cluster_policy);
+            if !moved.is_empty() {
+                (centroid_sums, centroid_counts) = cluster_sums(data, &clusters, k);
+                reset_moved_bounds(&moved, &mut upper_bounds, &mut lower_bounds);
+            }
+        }
+
+        move_centroids(
+            &centroids,
+            &mut new_centroids,
+            &centroid_sums,
+            |j| centroid_counts[j] as f32,
+            &mut centroid_move_distances,
+        );
+
+        if has_converged(&centroids, &new_centroids, config.tolerance) {
+            std::mem::swap(&mut centroids, &mut new_centroids);
+            break;
+        }
+        std::mem::swap(&mut centroids, &mut new_centroids);
+
+        for (group, members) in groups.members.iter().enumerate() {
+            group_move_distances[group] = members
+                .iter()
+                .map(|&j| centroid_move_distances[j])
+                .fold(EuclideanDistance(0.0), EuclideanDistance::max);
+        }
+
+        for (pixel, assigned_cluster, upper_bound, lower_bound) in izip!(
+            data,
+            &mut clusters,
+            &mut upper_bounds,
+            lower_bounds.chunks_exact_mut(num_groups)
+        ) {
+            *upper_bound += centroid_move_distances[*assigned_cluster];
+            for (bound, &distance) in lower_bound.iter_mut().zip(&group_move_distances) {
+                *bound -= distance;
+            }
+
+            // Global filter: if no group can beat the upper bound, the assignment holds.
+            let global_lower_bound = lower_bound
+                .iter()
+                .fold(EuclideanDistance(f32::MAX), |acc, &bound| acc.min(bound));
+            if *upper_bound <= global_lower_bound {
+                continue;
+            }
+
+            *upper_bound = euclidean_distance_squared(pixel, &centroids[*assigned_cluster]).sqrt();
+            if *upper_bound <= global_lower_bound {
+                continue;
+            }
+
+            // Group filter: only groups whose lower bound beats the upper bound are searched.
+            let previous_cluster = *assigned_cluster;
+            let previous_distance = *upper_bound;
+            let mut best_index = previous_cluster;
+            let mut best_distance = previous_distance;
+
+            for (group, members) in groups.members.iter().enumerate() {
+                group_visited[group] = lower_bound[group] < *upper_bound;
+                if !group_visited[group] {
+                    continue;
+                }
+
+                let mut best = (EuclideanDistance(f32::MAX), 0);
+                let mut second_best = EuclideanDistance(f32::MAX);
+                for &j in members {
+                    let distance = if j == previous_cluster {
+                        previous_distance
+                    } else {
+                        euclidean_distance_squared(pixel, &centroids[j]).sqrt()
+                    };
+                    if distance < best.0 {
+                        second_best = best.0;
+                        best = (distance, j);
+                    } else if distance < second_best {
+                        second_best = distance;
+                    }
+                }
+
+                if best.0 < best_distance {
+                    best_distance = best.0;
+                    best_index = best.1;
+                }
+                group_best[group] = best;
+                group_second_best[group] = second_best;
+            }
+
+            for group in 0..num_groups {
+                if group_visited[group] {
+                    // The bound excludes the assigned centroid, so use the runner-up
+                    // if the winner of this group is the new assignment.
+                    lower_bound[group] = if group_best[group].1 == best_index {
+                        group_second_best[group]
+                    } else {
+                        group_best[group].0
+                    };
+                } else if group == groups.group_of[previous_cluster]
+                    && best_index != previous_cluster
+                {
+                    // The old assignment now counts towards its group's bound.
+                    lower_bound[group] = lower_bound[group].min(previous_distance);
+                }
+            }
+
+            *upper_bound = best_distance;
+            if best_index != previous_cluster {
+                centroid_sums[previous_cluster] = centroid_sums[previous_cluster].sub(pixel);
+                centroid_counts[previous_cluster] -= 1;
+                centroid_sums[best_index] = centroid_sums[best_index].add(pixel);
+                centroid_counts[best_index] += 1;
+
+                *assigned_cluster = best_index;
+            }
+        }
+    }
+    finish_empty_clusters(clusters, centroids, config.empty_cluster_policy)
+}
+
+// Groups are formed once by clustering the initial centroids themselves.
+fn group_centroids<T: VectorExt>(centroids: &[T], config: &KMeansConfig) -> Groups {
+    let num_groups = (centroids.len() / CENTROIDS_PER_GROUP).max(1);
+
+    let group_of = if num_groups == 1 {
+        vec![0; centroids.len()]
+    } else {
+        let grouping_config = KMeansConfig {
+            k: num_groups,
+            max_iterations: GROUPING_ITERATIONS,
+            algorithm: KMeansAlgorithm::Lloyd,
+            initializer: Initializer::KMeansPlusPlus,
+            ..config.clone()
+        };
+        kmeans_lloyd(centroids, &grouping_config).0
+    };
+
+    let mut members = vec![Vec::new(); num_groups];
+    for (j, &group) in group_of.iter().enumerate() {
+        members[group].push(j);
+    }
+
+    // Lloyd can leave a group empty, depending on the empty cluster policy. Empty groups
+    // are harmless but useless, so drop them.
+    let mut remap = vec![0; num_groups];
+    let mut kept = 0;
+    for (group, group_members) in members.iter().enumerate() {
+        if !group_members.is_empty() {
+            remap[group] = kept;
+            kept += 1;
+        }
+    }
+    members.retain(|group_members| !group_members.is_empty());
+    let group_of = group_of.into_iter().map(|group| remap[group]).collect();
+
+    Groups { members, group_of }
+}
+
+fn initialize_yinyang<T: VectorExt>(
+    data: &[T],
+    centroids: &[T],
+    groups: &Groups,
+) -> (
+    CentroidSums<T>,
+    CentroidCounts,
+    UpperBounds,
+    GroupLowerBounds,
+    Assignments,
+) {
+    let k = centroids.len();
+    let num_groups = groups.members.len();
+    let num_pixels = data.len();
+
+    let mut clusters = vec![0; num_pixels];
+    let mut upper_bounds = vec![EuclideanDistance(0.0); num_pixels];
+    let mut lower_bounds = vec![EuclideanDistance(f32::MAX); num_pixels * num_groups];
+
+    let mut centroid_sums = vec![T::zero(); k];
+    let mut centroid_counts = vec![0; k];
+
+    let mut distances = vec![EuclideanDistance(0.0); k];
+
+    for (pixel, cluster, upper_bound, lower_bound) in izip!(
+        data,
+        &mut clusters,
+        &mut upper_bounds,
+        lower_bounds.chunks_exact_mut(num_groups)
+    ) {
+        let mut best_distance = EuclideanDistance(f32::MAX);
+        let mut best_index = 0;
+        for (j, centroid) in centroids.iter().enumerate() {
+            distances[j] = euclidean_distance_squared(pixel, centroid).sqrt();
+            if distances[j] < best_distance {
+                best_distance = distances[j];
+                best_index = j;
+            }
+        }
+
+        for (j, &distance) in distances.iter().enumerate() {
+            if j == best_index {
+                continue;
+            }
+            let group = groups.group_of[j];
+            lower_bound[group] = lower_bound[group].min(distance);
+        }
+
+        *upper_bound = best_distance;
+        *cluster = best_index;
+        centroid_sums[best_index] = centroid_sums[best_index].add(pixel);
+        centroid_counts[best_index] += 1;
+    }
+
+    (
+        centroid_sums,
+        centroid_counts,
+        upper_bounds,
+        lower_bounds,
+        clusters,
+    )
+}
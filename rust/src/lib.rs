@@ -3,8 +3,9 @@
 #[cfg(feature = "python")]
 pub mod python;
 
+pub mod color_space;
+pub mod histogram;
 pub mod kmeans;
 pub mod quantize;
 pub mod types;
-mod utils;
 pub mod wasm;
@@ -81,7 +81,9 @@ fn py_reduce_colorspace(
         .collect();
 
     let quantizer = ColorCruncher::new(num_colors as usize, sample_rate as usize, 3);
-    let data = quantizer.quantize_image(&flattened);
+    let data = quantizer
+        .quantize_image(&flattened, None)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.0))?;
 
     let reshaped = match numpy::ndarray::Array3::from_shape_vec((shape[0], shape[1], 3), data) {
         Ok(reshaped) => reshaped,
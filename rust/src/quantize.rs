@@ -1,10 +1,108 @@
+use crate::color_space::ColorSpace;
+use crate::histogram::ColorHistogram;
+use crate::kmeans::auto_k::{select_k, KSelection, KSelectionCriterion};
+use crate::kmeans::bisecting::bisecting_hierarchy;
+use crate::kmeans::distance::euclidean_distance_squared;
 use crate::kmeans::find_closest_centroid;
+use crate::kmeans::fuzzy::{blend, memberships, DEFAULT_FUZZIFIER};
+use crate::kmeans::snap_to_members;
+use crate::kmeans::EmptyClusterPolicy;
 use crate::kmeans::Initializer;
 use crate::kmeans::KMeans;
 use crate::kmeans::KMeansAlgorithm;
+use crate::kmeans::KMeansCPU;
 use crate::kmeans::KMeansConfig;
-use crate::types::Vec4u;
-use crate::utils::num_distinct_colors_u32;
+use crate::kmeans::KMeansError;
+use crate::types::{Vec4, Vec4u};
+use std::ops::RangeInclusive;
+
+// How pixels are mapped onto the palette
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RemapMode {
+    // Every pixel becomes its closest palette color
+    #[default]
+    Nearest,
+    // Every pixel becomes a mix of the top_n palette colors it has the highest fuzzy
+    // membership in. Smooths banding in gradients.
+    Blend {
+        top_n: usize,
+    },
+}
+
+// How much each pixel counts when building the palette, one weight per pixel. Weights
+// scale each pixel's pull on the palette colors, and pixels with a weight of 0 are left
+// out, which masks them. Weights must be finite and at least 0, and only their ratios
+// matter. Only Lloyd, Hamerly and PNN scale by weight, so other algorithms take a mask,
+// where every weight is either 0 or the same positive value.
+#[derive(Clone, Copy, Debug)]
+pub enum WeightMap<'a> {
+    U8(&'a [u8]),
+    F32(&'a [f32]),
+}
+
+impl WeightMap<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            WeightMap::U8(weights) => weights.len(),
+            WeightMap::F32(weights) => weights.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The first weight that is negative, infinite or NaN, if any
+    fn invalid_weight(&self) -> Option<f32> {
+        match self {
+            WeightMap::U8(_) => None,
+            WeightMap::F32(weights) => weights
+                .iter()
+                .copied()
+                .find(|weight| !weight.is_finite() || *weight < 0.0),
+        }
+    }
+
+    // Whether every positive weight is the same, so that the map only masks pixels
+    fn is_mask(&self) -> bool {
+        let mut positive: Vec<f32> = match self {
+            WeightMap::U8(weights) => weights.iter().map(|&weight| weight as f32).collect(),
+            WeightMap::F32(weights) => weights.to_vec(),
+        };
+        positive.retain(|&weight| weight > 0.0);
+        positive.windows(2).all(|pair| pair[0] == pair[1])
+    }
+
+    // The weights of the sampled pixels, scaled so that the largest is 1. Only the ratios
+    // matter, and large weights would overflow once summed per color.
+    fn sample(&self, sample_rate: usize) -> Vec<f32> {
+        let mut weights: Vec<f32> = match self {
+            WeightMap::U8(weights) => weights
+                .iter()
+                .step_by(sample_rate)
+                .map(|&weight| weight as f32)
+                .collect(),
+            WeightMap::F32(weights) => weights.iter().step_by(sample_rate).copied().collect(),
+        };
+        let max = weights.iter().copied().fold(0.0, f32::max);
+        if max > 0.0 {
+            weights.iter_mut().for_each(|weight| *weight /= max);
+        }
+        weights
+    }
+}
+
+impl<'a> From<&'a [u8]> for WeightMap<'a> {
+    fn from(weights: &'a [u8]) -> Self {
+        WeightMap::U8(weights)
+    }
+}
+
+impl<'a> From<&'a [f32]> for WeightMap<'a> {
+    fn from(weights: &'a [f32]) -> Self {
+        WeightMap::F32(weights)
+    }
+}
 
 #[derive(Debug)]
 pub struct ColorCruncher {
@@ -12,6 +110,10 @@ pub struct ColorCruncher {
     max_colors: usize,
     pub sample_rate: usize,
     pub channels: usize,
+    pub remap_mode: RemapMode,
+    // Replace each palette color with the closest color present in its cluster
+    pub snap_to_source: bool,
+    pub color_space: ColorSpace,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -24,6 +126,11 @@ pub struct ColorCruncherBuilder {
     pub initializer: Option<Initializer>,
     pub algorithm: Option<KMeansAlgorithm>,
     pub seed: Option<u64>,
+    pub n_init: Option<usize>,
+    pub empty_cluster_policy: Option<EmptyClusterPolicy>,
+    pub remap_mode: Option<RemapMode>,
+    pub snap_to_source: Option<bool>,
+    pub color_space: Option<ColorSpace>,
 }
 
 impl ColorCruncherBuilder {
@@ -71,15 +178,51 @@ impl ColorCruncherBuilder {
         self
     }
 
+    pub fn with_n_init(mut self, n_init: usize) -> Self {
+        self.n_init = Some(n_init);
+        self
+    }
+
+    pub fn with_empty_cluster_policy(mut self, policy: EmptyClusterPolicy) -> Self {
+        self.empty_cluster_policy = Some(policy);
+        self
+    }
+
+    pub fn with_remap_mode(mut self, remap_mode: RemapMode) -> Self {
+        self.remap_mode = Some(remap_mode);
+        self
+    }
+
+    pub fn with_snap_to_source(mut self, snap_to_source: bool) -> Self {
+        self.snap_to_source = Some(snap_to_source);
+        self
+    }
+
+    // Pixels are converted to the color space before clustering, and the palette is
+    // converted back to sRGB
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = Some(color_space);
+        self
+    }
+
     pub async fn build(&self) -> ColorCruncher {
         let kmeans_config = self.build_config();
         let kmeans = KMeans::new(kmeans_config.clone()).await;
 
+        // NeuQuant samples the image itself, so it gets every pixel
+        let sample_rate = match kmeans_config.algorithm {
+            KMeansAlgorithm::NeuQuant { .. } => 1,
+            _ => self.sample_rate.unwrap_or(1),
+        };
+
         ColorCruncher {
             kmeans,
             max_colors: kmeans_config.k,
-            sample_rate: self.sample_rate.unwrap_or(1),
+            sample_rate,
             channels: self.channels.unwrap_or(3),
+            remap_mode: self.remap_mode.unwrap_or_default(),
+            snap_to_source: self.snap_to_source.unwrap_or(false),
+            color_space: self.color_space.unwrap_or_default(),
         }
     }
 
@@ -95,11 +238,28 @@ impl ColorCruncherBuilder {
             .algorithm
             .clone()
             .unwrap_or_else(|| default_config.algorithm);
+        // The sample rate doubles as NeuQuant's sampling factor
+        if let (KMeansAlgorithm::NeuQuant { sample_factor }, Some(sample_rate)) =
+            (&mut config.algorithm, self.sample_rate)
+        {
+            *sample_factor = sample_rate;
+        }
         config.initializer = self
             .initializer
             .clone()
             .unwrap_or_else(|| default_config.initializer);
+        // Provided palettes are sRGB, but are used as centroids in the color space
+        if let Initializer::Provided(centroids) = &mut config.initializer {
+            let color_space = self.color_space.unwrap_or_default();
+            for centroid in centroids.iter_mut() {
+                *centroid = color_space.srgb_to_clustering(*centroid);
+            }
+        }
         config.seed = self.seed;
+        config.n_init = self.n_init.unwrap_or(default_config.n_init);
+        config.empty_cluster_policy = self
+            .empty_cluster_policy
+            .unwrap_or(default_config.empty_cluster_policy);
         config
     }
 }
@@ -120,26 +280,211 @@ impl ColorCruncher {
             .collect()
     }
 
-    pub async fn quantize_image(&self, pixels: &[u8]) -> Vec<u8> {
+    fn to_color_space(&self, pixels: &[Vec4u]) -> Vec<Vec4> {
+        pixels
+            .iter()
+            .map(|p| {
+                self.color_space.srgb_to_clustering([
+                    p[0] as f32,
+                    p[1] as f32,
+                    p[2] as f32,
+                    p[3] as f32,
+                ])
+            })
+            .collect()
+    }
+
+    fn to_palette(&self, centroids: &[Vec4]) -> Vec<[u8; 3]> {
+        centroids
+            .iter()
+            .map(|&color| {
+                let color = self.color_space.clustering_to_srgb(color);
+                [color[0] as u8, color[1] as u8, color[2] as u8]
+            })
+            .collect()
+    }
+
+    fn supports_weights(&self) -> bool {
+        matches!(&self.kmeans, KMeans::Cpu(cpu) if cpu.supports_weights())
+    }
+
+    // Runs the clustering, then snaps the centroids to source colors if enabled. `colors`
+    // and `weights` are the distinct colors of the pixels and how much each one counts.
+    // Returns the centroids, in the color space, and how far snapping moved each of them.
+    async fn run_kmeans(
+        &self,
+        pixels: &[Vec4u],
+        colors: Vec<Vec4>,
+        weights: Vec<f32>,
+    ) -> Result<(Vec<Vec4>, Vec<f32>), KMeansError> {
+        let (data, assignments, mut centroids) = match &self.kmeans {
+            // Cluster every distinct color once, weighted by how often it occurs
+            KMeans::Cpu(cpu) if self.supports_weights() => {
+                let colors: Vec<Vec4> = colors
+                    .iter()
+                    .map(|&color| self.color_space.srgb_to_clustering(color))
+                    .collect();
+                let (assignments, centroids) = cpu.run_weighted(&colors, &weights)?;
+                (colors, assignments, centroids)
+            }
+            KMeans::Cpu(cpu) => {
+                let data = self.to_color_space(pixels);
+                let (assignments, centroids) = cpu.run(&data)?;
+                (data, assignments, centroids)
+            }
+            // The shaders read whole numbers, so the GPU gets the converted pixels rounded
+            _ => {
+                let data = self.to_color_space(pixels);
+                let rounded: Vec<Vec4u> = data
+                    .iter()
+                    .map(|color| color.map(|channel| channel.round() as u32))
+                    .collect();
+                let (assignments, centroids) = self.kmeans.run_async(&rounded).await?;
+                (data, assignments, centroids)
+            }
+        };
+        if !self.snap_to_source {
+            return Ok((centroids, Vec::new()));
+        }
+
+        // The distances are measured between the sRGB colors, so that they mean the same
+        // in every color space
+        let unsnapped = centroids.clone();
+        snap_to_members(&data, &assignments, &mut centroids);
+        let distances = unsnapped
+            .iter()
+            .zip(centroids.iter())
+            .map(|(&before, &after)| {
+                let before = self.color_space.clustering_to_srgb(before);
+                let after = self.color_space.clustering_to_srgb(after);
+                euclidean_distance_squared(&before, &after).sqrt().0
+            })
+            .collect();
+        Ok((centroids, distances))
+    }
+
+    // Builds the palette from the pixels with a positive weight. A map that excludes
+    // every pixel is treated as no map at all.
+    async fn run_kmeans_weighted(
+        &self,
+        image_data: &[Vec4u],
+        histogram: &ColorHistogram,
+        weights: WeightMap<'_>,
+    ) -> Result<(Vec<Vec4>, Vec<f32>), KMeansError> {
+        let weights = weights.sample(self.sample_rate);
+        let masked = ColorHistogram::from_pixels_weighted(image_data, &weights);
+        if masked.is_empty() {
+            let (colors, counts) = histogram.weighted_colors();
+            return self.run_kmeans(image_data, colors, counts).await;
+        }
+
+        let (colors, color_weights) = masked.weighted_colors();
+        // The colors left after masking may already fit in the palette
+        if masked.len_rgb() <= self.max_colors {
+            let colors = colors
+                .iter()
+                .map(|&color| self.color_space.srgb_to_clustering(color))
+                .collect();
+            return Ok((colors, Vec::new()));
+        }
+
+        let included: Vec<Vec4u> = image_data
+            .iter()
+            .zip(weights.iter())
+            .filter(|(_, &weight)| weight > 0.0)
+            .map(|(pixel, _)| *pixel)
+            .collect();
+        self.run_kmeans(&included, colors, color_weights).await
+    }
+
+    // Weights, if given, hold one weight per pixel of the image. Only Lloyd, Hamerly and
+    // PNN scale pixels by weight. Other algorithms take a mask, and return an error for
+    // weights that vary. See WeightMap.
+    pub async fn quantize_image(
+        &self,
+        pixels: &[u8],
+        weights: Option<WeightMap<'_>>,
+    ) -> Result<Vec<u8>, KMeansError> {
+        let (image, _) = self
+            .quantize_image_with_snap_distances(pixels, weights)
+            .await?;
+        Ok(image)
+    }
+
+    // Like quantize_image, but also returns how far each palette color moved when
+    // snapped to a source color, as a Euclidean distance between sRGB colors with
+    // channels from 0 to 255, whatever the color space. The distances are empty when
+    // snapping is off or the image already fits in the palette.
+    pub async fn quantize_image_with_snap_distances(
+        &self,
+        pixels: &[u8],
+        weights: Option<WeightMap<'_>>,
+    ) -> Result<(Vec<u8>, Vec<f32>), KMeansError> {
+        let num_pixels = pixels.len() / self.channels;
+        if let Some(weights) = weights {
+            if weights.len() != num_pixels {
+                return Err(KMeansError(format!(
+                    "Expected one weight per pixel, got {} weights for {} pixels",
+                    weights.len(),
+                    num_pixels
+                )));
+            }
+            if let Some(weight) = weights.invalid_weight() {
+                return Err(KMeansError(format!(
+                    "Weights must be finite and at least 0, got {}",
+                    weight
+                )));
+            }
+            if !self.supports_weights() && !weights.is_mask() {
+                return Err(KMeansError(format!(
+                    "Only Lloyd, Hamerly and PNN scale pixels by weight, {} takes a 0/1 mask",
+                    self.kmeans.config().algorithm
+                )));
+            }
+        }
+
         let image_data = self.chunk_pixels_vec4u(pixels);
+        let histogram = ColorHistogram::from_pixels(&image_data);
 
         // If there's already less than or equal to the max number of colors, return the original pixels
-        if num_distinct_colors_u32(&image_data) <= self.max_colors {
-            return pixels.to_vec();
+        if histogram.len_rgb() <= self.max_colors {
+            return Ok((pixels.to_vec(), Vec::new()));
         }
 
-        let (_, centroids) = self.kmeans.run_async(&image_data).await.unwrap();
+        let (centroids, snap_distances) = match weights {
+            Some(weights) => {
+                self.run_kmeans_weighted(&image_data, &histogram, weights)
+                    .await?
+            }
+            None => {
+                let (colors, counts) = histogram.weighted_colors();
+                self.run_kmeans(&image_data, colors, counts).await?
+            }
+        };
+
+        // Blending uses the algorithm's own fuzzifier when it has one
+        let fuzzifier = match self.kmeans.config().algorithm {
+            KMeansAlgorithm::FuzzyCMeans { fuzzifier } => fuzzifier,
+            _ => DEFAULT_FUZZIFIER,
+        };
+        let mut row = vec![0.0; centroids.len()];
 
         let mut new_image = Vec::with_capacity(pixels.len());
         for pixel in pixels.chunks_exact(self.channels) {
-            let px_vec = [
+            let px_vec = self.color_space.srgb_to_clustering([
                 pixel[0] as f32,
                 pixel[1] as f32,
                 pixel[2] as f32,
                 pixel[3] as f32,
-            ];
-            let closest_centroid = find_closest_centroid(&px_vec, &centroids);
-            let new_color = &centroids[closest_centroid];
+            ]);
+            let new_color = match self.remap_mode {
+                RemapMode::Nearest => centroids[find_closest_centroid(&px_vec, &centroids)],
+                RemapMode::Blend { top_n } => {
+                    memberships(&px_vec, &centroids, fuzzifier, &mut row);
+                    blend(&row, &centroids, top_n)
+                }
+            };
+            let new_color = self.color_space.clustering_to_srgb(new_color);
 
             if self.channels == 3 {
                 new_image.extend_from_slice(&[
@@ -157,24 +502,72 @@ impl ColorCruncher {
             }
         }
 
-        new_image
+        Ok((new_image, snap_distances))
     }
 
-    pub async fn create_palette(&self, pixels: &[u8]) -> Vec<[u8; 3]> {
+    pub async fn create_palette(&self, pixels: &[u8]) -> Result<Vec<[u8; 3]>, KMeansError> {
         let image_data = self.chunk_pixels_vec4u(pixels);
+        let histogram = ColorHistogram::from_pixels(&image_data);
 
-        // If there's already less than or equal to the max number of colors, return the original pixels
-        if num_distinct_colors_u32(&image_data) < self.max_colors {
-            // todo
-            todo!()
+        // If there's already less than or equal to the max number of colors, they are the palette
+        if histogram.len_rgb() <= self.max_colors {
+            let mut palette = Vec::new();
+            for color_count in histogram.iter() {
+                let [r, g, b, _] = color_count.color;
+                if !palette.contains(&[r, g, b]) {
+                    palette.push([r, g, b]);
+                }
+            }
+            return Ok(palette);
         }
 
-        let (_, centroids) = self.kmeans.run_async(&image_data).await.unwrap();
-        centroids
+        let (colors, counts) = histogram.weighted_colors();
+        let (centroids, _) = self.run_kmeans(&image_data, colors, counts).await?;
+        Ok(self.to_palette(&centroids))
+    }
+
+    // Builds palettes of every size from 1 to max_colors in one bisecting k-means pass.
+    // Entry i has i + 1 colors, and each palette refines the one before it by splitting
+    // a single color in two.
+    pub fn create_palette_hierarchy(&self, pixels: &[u8]) -> Vec<Vec<[u8; 3]>> {
+        let image_data = self.to_color_space(&self.chunk_pixels_vec4u(pixels));
+
+        let (_, levels) = bisecting_hierarchy(&image_data, self.kmeans.config());
+        levels
             .iter()
-            .map(|color| [color[0] as u8, color[1] as u8, color[2] as u8])
+            .map(|centroids| self.to_palette(centroids))
             .collect()
     }
+
+    // Scores every palette size in the range and suggests one, for callers that
+    // don't know how many colors to ask for. The suggestion can be passed back in
+    // through ColorCruncherBuilder::with_max_colors.
+    // GPU algorithms are scored with Lloyd's algorithm on the CPU instead.
+    pub fn select_max_colors(
+        &self,
+        pixels: &[u8],
+        colors: RangeInclusive<usize>,
+        criterion: KSelectionCriterion,
+    ) -> Result<KSelection, KMeansError> {
+        let image_data = self.to_color_space(&self.chunk_pixels_vec4u(pixels));
+
+        let config = self.kmeans.config().clone();
+        let config = match config.algorithm {
+            #[cfg(feature = "gpu")]
+            KMeansAlgorithm::Gpu(_) => KMeansConfig {
+                algorithm: KMeansAlgorithm::Lloyd,
+                ..config
+            },
+            _ => config,
+        };
+
+        select_k(
+            &image_data,
+            &KMeansCPU::from_config(config),
+            colors,
+            criterion,
+        )
+    }
 }
 
 #[cfg(test)]
@@ -200,7 +593,371 @@ mod tests {
                 .build(),
         );
 
-        let result = block_on(quantizer.quantize_image(&data));
+        let result = block_on(quantizer.quantize_image(&data, None)).unwrap();
         assert_eq!(result.len(), data.len());
     }
+
+    #[test]
+    fn test_median_cut_is_deterministic() {
+        let data = vec![
+            255, 0, 0, 255, 250, 10, 0, 255, 0, 0, 255, 255, 10, 0, 240, 255, 0, 255, 0, 255, 5,
+            250, 5, 255,
+        ];
+
+        let quantizer = block_on(
+            ColorCruncherBuilder::default()
+                .with_max_colors(3)
+                .with_channels(4)
+                .with_algorithm(KMeansAlgorithm::MedianCut)
+                .build(),
+        );
+
+        let first = block_on(quantizer.quantize_image(&data, None)).unwrap();
+        let second = block_on(quantizer.quantize_image(&data, None)).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(&first[0..4], &first[4..8]);
+        assert_eq!(&first[8..12], &first[12..16]);
+    }
+
+    #[test]
+    fn test_sample_rate_maps_onto_neuquant_sample_factor() {
+        let quantizer = block_on(
+            ColorCruncherBuilder::default()
+                .with_max_colors(4)
+                .with_sample_rate(5)
+                .with_algorithm(KMeansAlgorithm::NeuQuant { sample_factor: 10 })
+                .build(),
+        );
+
+        assert_eq!(quantizer.sample_rate, 1);
+        assert!(matches!(
+            quantizer.kmeans.config().algorithm,
+            KMeansAlgorithm::NeuQuant { sample_factor: 5 }
+        ));
+    }
+
+    #[test]
+    fn test_blending_one_color_matches_nearest() {
+        let data: Vec<u8> = (0..64u8)
+            .flat_map(|i| [i * 4, 255 - i * 4, i * 2, 255])
+            .collect();
+        let builder = ColorCruncherBuilder::default()
+            .with_max_colors(4)
+            .with_channels(4)
+            .with_seed(3)
+            .with_algorithm(KMeansAlgorithm::FuzzyCMeans { fuzzifier: 2.0 });
+
+        let nearest = block_on(builder.clone().build());
+        let blended = block_on(
+            builder
+                .with_remap_mode(RemapMode::Blend { top_n: 1 })
+                .build(),
+        );
+
+        assert_eq!(
+            block_on(nearest.quantize_image(&data, None)).unwrap(),
+            block_on(blended.quantize_image(&data, None)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_snapped_palette_only_uses_source_colors() {
+        let data: Vec<u8> = (0..64u8)
+            .flat_map(|i| [i * 4, 255 - i * 4, i * 2, 255])
+            .collect();
+
+        let quantizer = block_on(
+            ColorCruncherBuilder::default()
+                .with_max_colors(4)
+                .with_channels(4)
+                .with_seed(5)
+                .with_snap_to_source(true)
+                .build(),
+        );
+        let (result, distances) =
+            block_on(quantizer.quantize_image_with_snap_distances(&data, None)).unwrap();
+
+        assert_eq!(distances.len(), 4);
+        assert!(distances.iter().all(|&distance| distance >= 0.0));
+        for pixel in result.chunks_exact(4) {
+            assert!(data.chunks_exact(4).any(|source| source == pixel));
+        }
+    }
+
+    #[test]
+    fn test_snap_distances_are_in_srgb_units() {
+        let data = vec![
+            0, 0, 0, 255, 30, 0, 0, 255, 200, 200, 200, 255, 230, 200, 200, 255,
+        ];
+        let color_spaces = [
+            ColorSpace::Srgb,
+            ColorSpace::LinearRgb,
+            ColorSpace::Lab,
+            ColorSpace::Oklab,
+        ];
+        for color_space in color_spaces {
+            let builder = ColorCruncherBuilder::default()
+                .with_max_colors(2)
+                .with_channels(4)
+                .with_seed(5)
+                .with_color_space(color_space);
+            let unsnapped = block_on(builder.build());
+            let unsnapped = block_on(unsnapped.quantize_image(&data, None)).unwrap();
+            let snapped = block_on(builder.with_snap_to_source(true).build());
+            let (snapped, mut distances) =
+                block_on(snapped.quantize_image_with_snap_distances(&data, None)).unwrap();
+
+            // Pixels 0 and 2 fall in different clusters
+            let mut expected: Vec<f32> = [0, 8]
+                .iter()
+                .map(|&i| {
+                    (0..3)
+                        .map(|c| (unsnapped[i + c] as f32 - snapped[i + c] as f32).powi(2))
+                        .sum::<f32>()
+                        .sqrt()
+                })
+                .collect();
+            expected.sort_by(f32::total_cmp);
+            distances.sort_by(f32::total_cmp);
+            for (distance, expected) in distances.iter().zip(expected.iter()) {
+                assert!((distance - expected).abs() < 1e-3, "{:?}", color_space);
+            }
+        }
+    }
+
+    #[test]
+    fn test_weights_scale_and_mask_pixels() {
+        let data = vec![
+            0, 0, 0, 255, 60, 60, 60, 255, 240, 240, 240, 255, 0, 0, 250, 255,
+        ];
+        let quantizer = block_on(
+            ColorCruncherBuilder::default()
+                .with_max_colors(1)
+                .with_channels(4)
+                .build(),
+        );
+
+        // The blue pixel is masked out, and black counts twice
+        let weights: &[f32] = &[2.0, 1.0, 1.0, 0.0];
+        let result = block_on(quantizer.quantize_image(&data, Some(weights.into()))).unwrap();
+
+        for pixel in result.chunks_exact(4) {
+            assert_eq!(pixel, [75, 75, 75, 255]);
+        }
+
+        let mask: &[u8] = &[0, 0, 0, 1];
+        let result = block_on(quantizer.quantize_image(&data, Some(mask.into()))).unwrap();
+        assert_eq!(&result[0..4], [0, 0, 250, 255]);
+
+        // An empty mask is no mask, and a mask of the wrong size is an error
+        let empty: &[u8] = &[0; 4];
+        assert_eq!(
+            block_on(quantizer.quantize_image(&data, Some(empty.into()))).unwrap(),
+            block_on(quantizer.quantize_image(&data, None)).unwrap()
+        );
+        let short: &[u8] = &[1; 3];
+        assert!(block_on(quantizer.quantize_image(&data, Some(short.into()))).is_err());
+
+        // Weights that aren't finite or are negative are errors, and huge ones still work
+        for weight in [f32::INFINITY, f32::NAN, -1.0] {
+            let weights: &[f32] = &[1.0, weight, 1.0, 1.0];
+            assert!(block_on(quantizer.quantize_image(&data, Some(weights.into()))).is_err());
+        }
+        let huge: &[f32] = &[f32::MAX, f32::MAX / 2.0, f32::MAX / 2.0, 0.0];
+        assert_eq!(
+            block_on(quantizer.quantize_image(&data, Some(huge.into()))).unwrap(),
+            block_on(quantizer.quantize_image(&data, Some(weights.into()))).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_clustering_errors_are_returned() {
+        let data = vec![
+            0, 0, 0, 255, 60, 60, 60, 255, 240, 240, 240, 255, 0, 0, 250, 255,
+        ];
+        let quantizer = block_on(
+            ColorCruncherBuilder::default()
+                .with_max_colors(0)
+                .with_channels(4)
+                .build(),
+        );
+        assert!(block_on(quantizer.quantize_image(&data, None)).is_err());
+        assert!(block_on(quantizer.create_palette(&data)).is_err());
+
+        // Weights that vary need an algorithm that scales by them
+        let quantizer = block_on(
+            ColorCruncherBuilder::default()
+                .with_max_colors(2)
+                .with_channels(4)
+                .with_algorithm(KMeansAlgorithm::MedianCut)
+                .build(),
+        );
+        let mask: &[u8] = &[255, 0, 255, 255];
+        assert!(block_on(quantizer.quantize_image(&data, Some(mask.into()))).is_ok());
+        let weights: &[u8] = &[255, 0, 128, 255];
+        assert!(block_on(quantizer.quantize_image(&data, Some(weights.into()))).is_err());
+    }
+
+    #[test]
+    fn test_perceptual_palettes_convert_back_to_srgb() {
+        let data: Vec<u8> = (0..64u8)
+            .flat_map(|i| [i * 4, 255 - i * 4, i * 2, 255])
+            .collect();
+
+        for color_space in [ColorSpace::LinearRgb, ColorSpace::Lab, ColorSpace::Oklab] {
+            let quantizer = block_on(
+                ColorCruncherBuilder::default()
+                    .with_max_colors(4)
+                    .with_channels(4)
+                    .with_seed(5)
+                    .with_color_space(color_space)
+                    .with_snap_to_source(true)
+                    .build(),
+            );
+            let result = block_on(quantizer.quantize_image(&data, None)).unwrap();
+
+            // Snapped colors survive the round trip through the color space exactly
+            let mut palette: Vec<&[u8]> = result.chunks_exact(4).collect();
+            palette.sort();
+            palette.dedup();
+            assert!(palette.len() <= 4);
+            for color in palette {
+                assert!(data.chunks_exact(4).any(|source| source == color));
+            }
+        }
+    }
+
+    #[test]
+    fn test_palette_of_an_image_that_already_fits() {
+        let data = vec![
+            255, 0, 0, 255, 0, 0, 255, 255, 255, 0, 0, 128, 0, 0, 255, 255,
+        ];
+        let quantizer = block_on(
+            ColorCruncherBuilder::default()
+                .with_max_colors(2)
+                .with_channels(4)
+                .build(),
+        );
+
+        let palette = block_on(quantizer.create_palette(&data)).unwrap();
+        assert_eq!(palette, vec![[255, 0, 0], [0, 0, 255]]);
+    }
+
+    #[test]
+    fn test_dark_gradient_in_every_color_space() {
+        // Shades this close together round to the same byte once converted to linear RGB
+        let data: Vec<u8> = (0..16u8).flat_map(|i| [i, i, i, 255]).collect();
+
+        let algorithms = [
+            KMeansAlgorithm::Lloyd,
+            KMeansAlgorithm::Hamerly,
+            KMeansAlgorithm::Elkan,
+            KMeansAlgorithm::Yinyang,
+            KMeansAlgorithm::KMedoids,
+            KMeansAlgorithm::Wu,
+        ];
+        let color_spaces = [
+            ColorSpace::Srgb,
+            ColorSpace::LinearRgb,
+            ColorSpace::Lab,
+            ColorSpace::Oklab,
+        ];
+        for algorithm in algorithms {
+            for color_space in color_spaces {
+                let quantizer = block_on(
+                    ColorCruncherBuilder::default()
+                        .with_max_colors(4)
+                        .with_channels(4)
+                        .with_seed(3)
+                        .with_algorithm(algorithm.clone())
+                        .with_color_space(color_space)
+                        .build(),
+                );
+                let result = block_on(quantizer.quantize_image(&data, None));
+                assert!(result.is_ok(), "{:?} {:?}", algorithm, color_space);
+            }
+        }
+    }
+
+    #[test]
+    fn test_byte_range_algorithms_in_every_color_space() {
+        // Shades of green, blue and cyan, whose a and b are negative in Lab and OKLab
+        let data: Vec<u8> = (0..768u32)
+            .flat_map(|i| {
+                let shade = (64 + (i / 3) * 3 / 4) as u8;
+                match i % 3 {
+                    0 => [0, shade, 0, 255],
+                    1 => [0, 0, shade, 255],
+                    _ => [0, shade, shade, 255],
+                }
+            })
+            .collect();
+        let mse = |result: &[u8]| {
+            let total: f32 = data
+                .iter()
+                .zip(result.iter())
+                .map(|(&a, &b)| (a as f32 - b as f32).powi(2))
+                .sum();
+            total / (data.len() / 4) as f32
+        };
+
+        let algorithms = [
+            KMeansAlgorithm::Octree,
+            KMeansAlgorithm::Wu,
+            KMeansAlgorithm::NeuQuant { sample_factor: 1 },
+        ];
+        for algorithm in &algorithms {
+            let run = |color_space| {
+                let quantizer = block_on(
+                    ColorCruncherBuilder::default()
+                        .with_max_colors(8)
+                        .with_channels(4)
+                        .with_algorithm(algorithm.clone())
+                        .with_color_space(color_space)
+                        .build(),
+                );
+                block_on(quantizer.quantize_image(&data, None)).unwrap()
+            };
+            let srgb = mse(&run(ColorSpace::Srgb));
+
+            // Each space has its own idea of close, so only ask for a comparable error.
+            // Clamped a and b turned these into browns and grays, many times the error.
+            for color_space in [ColorSpace::LinearRgb, ColorSpace::Lab, ColorSpace::Oklab] {
+                let result = run(color_space);
+                let red = result.chunks_exact(4).map(|pixel| pixel[0]).max();
+                assert!(red < Some(32), "{:?} {:?}", algorithm, color_space);
+                assert!(
+                    mse(&result) < 5.0 * srgb,
+                    "{:?} {:?}",
+                    algorithm,
+                    color_space
+                );
+            }
+        }
+
+        // Dark shades share a bin once fitted into 0-255, and the palette is topped up
+        let dark: Vec<u8> = (0..16u8).flat_map(|i| [i, i, i, 255]).collect();
+        for algorithm in algorithms {
+            for color_space in [
+                ColorSpace::Srgb,
+                ColorSpace::LinearRgb,
+                ColorSpace::Lab,
+                ColorSpace::Oklab,
+            ] {
+                let quantizer = block_on(
+                    ColorCruncherBuilder::default()
+                        .with_max_colors(4)
+                        .with_channels(4)
+                        .with_algorithm(algorithm.clone())
+                        .with_color_space(color_space)
+                        .build(),
+                );
+                let result = block_on(quantizer.quantize_image(&dark, None)).unwrap();
+                let mut palette: Vec<&[u8]> = result.chunks_exact(4).collect();
+                palette.sort();
+                palette.dedup();
+                assert_eq!(palette.len(), 4, "{:?} {:?}", algorithm, color_space);
+            }
+        }
+    }
 }
@@ -6,13 +6,18 @@ pub trait GPUVector {}
 pub trait VectorExt:
     Clone + Copy + std::ops::Index<usize, Output = f32> + std::ops::IndexMut<usize> + std::fmt::Debug
 {
+    const CHANNELS: usize;
+
     fn add(&self, other: &Self) -> Self;
     fn sub(&self, other: &Self) -> Self;
     fn div_scalar(&self, scalar: f32) -> Self;
+    fn mul_scalar(&self, scalar: f32) -> Self;
     fn zero() -> Self;
 }
 
 impl VectorExt for Vec3 {
+    const CHANNELS: usize = 3;
+
     fn zero() -> Self {
         [0.0; 3]
     }
@@ -29,6 +34,10 @@ impl VectorExt for Vec3 {
         [self[0] / scalar, self[1] / scalar, self[2] / scalar]
     }
 
+    fn mul_scalar(&self, scalar: f32) -> Self {
+        [self[0] * scalar, self[1] * scalar, self[2] * scalar]
+    }
+
     fn sub(&self, other: &Vec3) -> Self {
         let mut sum = [0.0; 3];
         for i in 0..3 {
@@ -39,6 +48,8 @@ impl VectorExt for Vec3 {
 }
 
 impl VectorExt for Vec4 {
+    const CHANNELS: usize = 4;
+
     fn add(&self, other: &Vec4) -> Self {
         let mut sum = [0.0; 4];
         for i in 0..4 {
@@ -64,6 +75,15 @@ impl VectorExt for Vec4 {
         ]
     }
 
+    fn mul_scalar(&self, scalar: f32) -> Self {
+        [
+            self[0] * scalar,
+            self[1] * scalar,
+            self[2] * scalar,
+            self[3] * scalar,
+        ]
+    }
+
     fn zero() -> Self {
         [0.0; 4]
     }
@@ -5,8 +5,10 @@
 const RGBA_CHANNELS: usize = 4;
 use js_sys::Uint8Array;
 
+use crate::histogram::ColorHistogram;
+use crate::kmeans::auto_k::{KSelection, KSelectionCriterion, DEFAULT_SILHOUETTE_SAMPLE_SIZE};
 use crate::kmeans::gpu::GpuAlgorithm;
-use crate::quantize::{ColorCruncher, ColorCruncherBuilder};
+use crate::quantize::{ColorCruncher, ColorCruncherBuilder, RemapMode, WeightMap};
 use console_error_panic_hook;
 use console_log;
 use log::Level;
@@ -27,14 +29,34 @@ pub struct WasmColorCruncher(ColorCruncher);
 #[wasm_bindgen(js_name = ColorCruncherBuilder)]
 pub struct WasmColorCruncherBuilder(ColorCruncherBuilder);
 
+#[wasm_bindgen(js_name = ColorHistogram)]
+pub struct WasmColorHistogram(ColorHistogram);
+
+#[wasm_bindgen(js_name = KSelection)]
+pub struct WasmKSelection(KSelection);
+
 #[wasm_bindgen(typescript_custom_section)]
 const TS_APPEND_CONTENT: &'static str = r#"
-export type Algorithm = "lloyd" | "hamerly" | "lloyd-all-gpu" | "lloyd-assignment-gpu";
-export type Initializer = "kmeans++" | "random";
+export type Algorithm = "lloyd" | "hamerly" | "elkan" | "yinyang" | "minibatch" | "median-cut" | "wu" | "octree" | "neuquant" | "bisecting" | "pnn" | "x-means" | "g-means" | "fuzzy-c-means" | "gmm-diagonal" | "gmm-full" | "k-medoids" | "lloyd-all-gpu" | "lloyd-assignment-gpu";
+export type Initializer = "kmeans++" | "random" | "median-cut" | "wu" | "kmeans||" | "greedy-kmeans++" | "maximin" | "variance-partition" | "pca-partition";
+export type ColorSpace = "srgb" | "linear-rgb" | "lab" | "oklab";
+export type KSelectionCriterion = "elbow" | "silhouette" | "bic";
 "#;
 
 type Algorithm = String;
 type Initializer = String;
+type ColorSpace = String;
+type Criterion = String;
+
+fn parse_color_space(color_space: &str) -> crate::color_space::ColorSpace {
+    match color_space {
+        "srgb" => crate::color_space::ColorSpace::Srgb,
+        "linear-rgb" => crate::color_space::ColorSpace::LinearRgb,
+        "lab" => crate::color_space::ColorSpace::Lab,
+        "oklab" => crate::color_space::ColorSpace::Oklab,
+        _ => panic!("Invalid color space: {}", color_space),
+    }
+}
 
 #[wasm_bindgen(js_class = ColorCruncherBuilder)]
 impl WasmColorCruncherBuilder {
@@ -88,6 +110,13 @@ impl WasmColorCruncherBuilder {
         let init = match initializer.as_str() {
             "kmeans++" => crate::kmeans::Initializer::KMeansPlusPlus,
             "random" => crate::kmeans::Initializer::Random,
+            "median-cut" => crate::kmeans::Initializer::MedianCut,
+            "wu" => crate::kmeans::Initializer::Wu,
+            "kmeans||" => crate::kmeans::Initializer::KMeansParallel,
+            "greedy-kmeans++" => crate::kmeans::Initializer::GreedyKMeansPlusPlus { trials: 0 },
+            "maximin" => crate::kmeans::Initializer::Maximin,
+            "variance-partition" => crate::kmeans::Initializer::VariancePartition,
+            "pca-partition" => crate::kmeans::Initializer::PcaPartition,
             _ => panic!("Invalid initializer: {}", initializer),
         };
         Self(self.0.with_initializer(init))
@@ -98,6 +127,13 @@ impl WasmColorCruncherBuilder {
         let init = match initializer.as_str() {
             "kmeans++" => crate::kmeans::Initializer::KMeansPlusPlus,
             "random" => crate::kmeans::Initializer::Random,
+            "median-cut" => crate::kmeans::Initializer::MedianCut,
+            "wu" => crate::kmeans::Initializer::Wu,
+            "kmeans||" => crate::kmeans::Initializer::KMeansParallel,
+            "greedy-kmeans++" => crate::kmeans::Initializer::GreedyKMeansPlusPlus { trials: 0 },
+            "maximin" => crate::kmeans::Initializer::Maximin,
+            "variance-partition" => crate::kmeans::Initializer::VariancePartition,
+            "pca-partition" => crate::kmeans::Initializer::PcaPartition,
             _ => panic!("Invalid initializer: {}", initializer),
         };
         self.0.initializer = Some(init);
@@ -108,6 +144,35 @@ impl WasmColorCruncherBuilder {
         let algo = match algorithm.as_str() {
             "lloyd" => crate::kmeans::KMeansAlgorithm::Lloyd,
             "hamerly" => crate::kmeans::KMeansAlgorithm::Hamerly,
+            "elkan" => crate::kmeans::KMeansAlgorithm::Elkan,
+            "yinyang" => crate::kmeans::KMeansAlgorithm::Yinyang,
+            "minibatch" => crate::kmeans::KMeansAlgorithm::MiniBatch {
+                batch_size: crate::kmeans::minibatch::DEFAULT_BATCH_SIZE,
+            },
+            "median-cut" => crate::kmeans::KMeansAlgorithm::MedianCut,
+            "wu" => crate::kmeans::KMeansAlgorithm::Wu,
+            "octree" => crate::kmeans::KMeansAlgorithm::Octree,
+            "neuquant" => crate::kmeans::KMeansAlgorithm::NeuQuant {
+                sample_factor: crate::kmeans::neuquant::DEFAULT_SAMPLE_FACTOR,
+            },
+            "bisecting" => crate::kmeans::KMeansAlgorithm::Bisecting,
+            "pnn" => crate::kmeans::KMeansAlgorithm::Pnn,
+            "x-means" => crate::kmeans::KMeansAlgorithm::Adaptive {
+                split_test: crate::kmeans::adaptive::SplitTest::Bic,
+            },
+            "g-means" => crate::kmeans::KMeansAlgorithm::Adaptive {
+                split_test: crate::kmeans::adaptive::SplitTest::AndersonDarling,
+            },
+            "fuzzy-c-means" => crate::kmeans::KMeansAlgorithm::FuzzyCMeans {
+                fuzzifier: crate::kmeans::fuzzy::DEFAULT_FUZZIFIER,
+            },
+            "gmm-diagonal" => crate::kmeans::KMeansAlgorithm::GaussianMixture {
+                covariance: crate::kmeans::gmm::CovarianceType::Diagonal,
+            },
+            "gmm-full" => crate::kmeans::KMeansAlgorithm::GaussianMixture {
+                covariance: crate::kmeans::gmm::CovarianceType::Full,
+            },
+            "k-medoids" => crate::kmeans::KMeansAlgorithm::KMedoids,
             "lloyd-all-gpu" => GpuAlgorithm::LloydAssignmentsAndCentroids.into(),
             "lloyd-assignment-gpu" => GpuAlgorithm::LloydAssignmentsOnly.into(),
             _ => panic!("Invalid algorithm: {}", algorithm),
@@ -120,6 +185,35 @@ impl WasmColorCruncherBuilder {
         let algo = match algorithm.as_str() {
             "lloyd" => crate::kmeans::KMeansAlgorithm::Lloyd,
             "hamerly" => crate::kmeans::KMeansAlgorithm::Hamerly,
+            "elkan" => crate::kmeans::KMeansAlgorithm::Elkan,
+            "yinyang" => crate::kmeans::KMeansAlgorithm::Yinyang,
+            "minibatch" => crate::kmeans::KMeansAlgorithm::MiniBatch {
+                batch_size: crate::kmeans::minibatch::DEFAULT_BATCH_SIZE,
+            },
+            "median-cut" => crate::kmeans::KMeansAlgorithm::MedianCut,
+            "wu" => crate::kmeans::KMeansAlgorithm::Wu,
+            "octree" => crate::kmeans::KMeansAlgorithm::Octree,
+            "neuquant" => crate::kmeans::KMeansAlgorithm::NeuQuant {
+                sample_factor: crate::kmeans::neuquant::DEFAULT_SAMPLE_FACTOR,
+            },
+            "bisecting" => crate::kmeans::KMeansAlgorithm::Bisecting,
+            "pnn" => crate::kmeans::KMeansAlgorithm::Pnn,
+            "x-means" => crate::kmeans::KMeansAlgorithm::Adaptive {
+                split_test: crate::kmeans::adaptive::SplitTest::Bic,
+            },
+            "g-means" => crate::kmeans::KMeansAlgorithm::Adaptive {
+                split_test: crate::kmeans::adaptive::SplitTest::AndersonDarling,
+            },
+            "fuzzy-c-means" => crate::kmeans::KMeansAlgorithm::FuzzyCMeans {
+                fuzzifier: crate::kmeans::fuzzy::DEFAULT_FUZZIFIER,
+            },
+            "gmm-diagonal" => crate::kmeans::KMeansAlgorithm::GaussianMixture {
+                covariance: crate::kmeans::gmm::CovarianceType::Diagonal,
+            },
+            "gmm-full" => crate::kmeans::KMeansAlgorithm::GaussianMixture {
+                covariance: crate::kmeans::gmm::CovarianceType::Full,
+            },
+            "k-medoids" => crate::kmeans::KMeansAlgorithm::KMedoids,
             "lloyd-all-gpu" => GpuAlgorithm::LloydAssignmentsAndCentroids.into(),
             "lloyd-assignment-gpu" => GpuAlgorithm::LloydAssignmentsOnly.into(),
             _ => panic!("Invalid algorithm: {}", algorithm),
@@ -137,6 +231,57 @@ impl WasmColorCruncherBuilder {
         self.0.seed = Some(seed);
     }
 
+    // Run k-means this many times from different seeds and keep the best palette
+    #[wasm_bindgen(js_name = withNInit)]
+    pub fn with_n_init(self, n_init: u32) -> Self {
+        Self(self.0.with_n_init(n_init as usize))
+    }
+
+    #[wasm_bindgen(js_name = setNInit)]
+    pub fn set_n_init(&mut self, n_init: u32) {
+        self.0.n_init = Some(n_init as usize);
+    }
+
+    // Mix the top_n closest palette colors instead of snapping to one. 0 turns blending off.
+    #[wasm_bindgen(js_name = withBlendedRemap)]
+    pub fn with_blended_remap(self, top_n: u32) -> Self {
+        let remap_mode = match top_n {
+            0 => RemapMode::Nearest,
+            top_n => RemapMode::Blend {
+                top_n: top_n as usize,
+            },
+        };
+        Self(self.0.with_remap_mode(remap_mode))
+    }
+
+    // Warm start from an existing palette, given as packed RGB triples
+    #[wasm_bindgen(js_name = withInitialPalette)]
+    pub fn with_initial_palette(self, palette: &[u8]) -> Self {
+        let centroids = palette
+            .chunks_exact(3)
+            .map(|color| [color[0] as f32, color[1] as f32, color[2] as f32, 255.0])
+            .collect();
+        Self(
+            self.0
+                .with_initializer(crate::kmeans::Initializer::Provided(centroids)),
+        )
+    }
+
+    #[wasm_bindgen(js_name = withColorSpace)]
+    pub fn with_color_space(self, color_space: ColorSpace) -> Self {
+        Self(self.0.with_color_space(parse_color_space(&color_space)))
+    }
+
+    #[wasm_bindgen(js_name = setColorSpace)]
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.0.color_space = Some(parse_color_space(&color_space));
+    }
+
+    #[wasm_bindgen(js_name = withSnapToSource)]
+    pub fn with_snap_to_source(self, snap_to_source: bool) -> Self {
+        Self(self.0.with_snap_to_source(snap_to_source))
+    }
+
     #[wasm_bindgen(js_name = build)]
     pub async fn build(&self) -> WasmColorCruncher {
         WasmColorCruncher(self.0.build().await)
@@ -152,9 +297,43 @@ impl WasmColorCruncher {
             .with_sample_rate(sample_rate)
     }
 
+    // Scores every palette size from min_colors to max_colors and suggests one
+    #[wasm_bindgen(js_name = selectMaxColors)]
+    pub fn select_max_colors(
+        &self,
+        data: &[u8],
+        min_colors: u32,
+        max_colors: u32,
+        criterion: Criterion,
+    ) -> Result<WasmKSelection, String> {
+        let criterion = match criterion.as_str() {
+            "elbow" => KSelectionCriterion::Elbow,
+            "silhouette" => KSelectionCriterion::Silhouette {
+                sample_size: DEFAULT_SILHOUETTE_SAMPLE_SIZE,
+            },
+            "bic" => KSelectionCriterion::Bic,
+            _ => return Err(format!("Invalid criterion: {}", criterion)),
+        };
+        self.0
+            .select_max_colors(data, min_colors as usize..=max_colors as usize, criterion)
+            .map(WasmKSelection)
+            .map_err(|error| error.0)
+    }
+
+    // Weights, if given, hold one weight per pixel, finite and at least 0. Pixels with
+    // weight 0 are left out of the palette, unless every weight is 0.
     #[wasm_bindgen(js_name = quantizeImage)]
-    pub async fn quantize_image(&self, data: &[u8]) -> Result<Uint8Array, String> {
-        let result = self.0.quantize_image(data).await;
+    pub async fn quantize_image(
+        &self,
+        data: &[u8],
+        weights: Option<Vec<f32>>,
+    ) -> Result<Uint8Array, String> {
+        let weights = weights.as_deref().map(WeightMap::F32);
+        let result = self
+            .0
+            .quantize_image(data, weights)
+            .await
+            .map_err(|error| error.0)?;
         Ok(Uint8Array::from(result.as_slice()))
     }
 
@@ -165,3 +344,76 @@ impl WasmColorCruncher {
     //     // Ok(palette.iter().map(|color| Uint8Array::from(color.to_vec())).collect())
     // }
 }
+
+#[wasm_bindgen(js_class = ColorHistogram)]
+impl WasmColorHistogram {
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: &[u8]) -> WasmColorHistogram {
+        WasmColorHistogram(ColorHistogram::from_bytes(data, RGBA_CHANNELS))
+    }
+
+    // Number of distinct colors
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> u32 {
+        self.0.len() as u32
+    }
+
+    // Number of pixels counted
+    #[wasm_bindgen(getter)]
+    pub fn total(&self) -> u32 {
+        self.0.total() as u32
+    }
+
+    // The n most common colors as flat RGBA bytes, most common first
+    #[wasm_bindgen(js_name = topColors)]
+    pub fn top_colors(&self, n: u32) -> Uint8Array {
+        let colors: Vec<u8> = self
+            .0
+            .top(n as usize)
+            .iter()
+            .flat_map(|color| color.color)
+            .collect();
+        Uint8Array::from(colors.as_slice())
+    }
+
+    // Pixel counts of the colors returned by topColors
+    #[wasm_bindgen(js_name = topCounts)]
+    pub fn top_counts(&self, n: u32) -> Vec<u32> {
+        self.0
+            .top(n as usize)
+            .iter()
+            .map(|color| color.count as u32)
+            .collect()
+    }
+
+    // Percentage of the image covered by each of the colors returned by topColors
+    #[wasm_bindgen(js_name = topCoverage)]
+    pub fn top_coverage(&self, n: u32) -> Vec<f32> {
+        self.0
+            .top(n as usize)
+            .iter()
+            .map(|color| color.coverage)
+            .collect()
+    }
+}
+
+#[wasm_bindgen(js_class = KSelection)]
+impl WasmKSelection {
+    // The suggested number of colors
+    #[wasm_bindgen(getter)]
+    pub fn k(&self) -> u32 {
+        self.0.k as u32
+    }
+
+    // Every palette size that was scored, in increasing order
+    #[wasm_bindgen(getter)]
+    pub fn ks(&self) -> Vec<u32> {
+        self.0.scores.iter().map(|&(k, _)| k as u32).collect()
+    }
+
+    // The score of each palette size in ks
+    #[wasm_bindgen(getter)]
+    pub fn scores(&self) -> Vec<f32> {
+        self.0.scores.iter().map(|&(_, score)| score).collect()
+    }
+}